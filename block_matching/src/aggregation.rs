@@ -0,0 +1,84 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How the per-pixel costs are combined before the disparity with the
+/// smallest cost is picked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Costs summed over each block. Fast, but blocky and noisy where there
+    /// is little texture.
+    #[default]
+    Box,
+    /// Semi-global matching: costs accumulated along `SgmPaths` directions
+    /// through each pixel, penalizing disparity changes between neighbors by
+    /// the matcher's `sgm_penalties`. Always dense and per pixel, so the
+    /// block size is unused; needs the stored cost volume and a per-pixel
+    /// cost, which rules out ZNCC.
+    Sgm,
+    /// Yoon-Kweon adaptive support weights: costs averaged over each block
+    /// centered on a pixel, weighted by how close in RGB and in position each
+    /// block pixel is to the center in both images, so that blocks do not
+    /// blur across object edges. Uses the pair's color when it has one and
+    /// the matcher's `adaptive_weights`. Always dense; rules out ZNCC.
+    AdaptiveWeights,
+    /// Cross-based support regions: each pixel gets arms reaching left,
+    /// right, up and down while colors stay within the matcher's
+    /// `cross_arms` threshold, and costs are summed along the horizontal arms
+    /// of the pixels on its vertical arm. Shape-adaptive at about the cost of
+    /// a box; the block size is unused. Always dense; rules out ZNCC.
+    Cross,
+    /// Guided filter over each disparity slice of the cost volume, with the
+    /// block as window and the right image, which the volume is laid out
+    /// by, as guide: an edge-preserving box filter. Its smoothing falls with
+    /// the matcher's `guided_filter_epsilon`. Always dense; rules out ZNCC.
+    /// Needs the stored cost volume and takes up to 24 more bytes per cost
+    /// for its integral images and coefficients.
+    Guided,
+    /// The sums of `Box`, taken from per-disparity integral images of the
+    /// stored cost volume in constant time whatever the block size, so that
+    /// large blocks stay fast. Takes 4 more bytes per cost and rules out
+    /// ZNCC, whose window statistics are not in the volume.
+    IntegralBox,
+}
+
+impl Aggregation {
+    pub fn all() -> [Aggregation; 6] {
+        [
+            Aggregation::Box,
+            Aggregation::Sgm,
+            Aggregation::AdaptiveWeights,
+            Aggregation::Cross,
+            Aggregation::Guided,
+            Aggregation::IntegralBox,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Aggregation::Box => "box",
+            Aggregation::Sgm => "sgm",
+            Aggregation::AdaptiveWeights => "adaptive",
+            Aggregation::Cross => "cross",
+            Aggregation::Guided => "guided",
+            Aggregation::IntegralBox => "integral",
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Aggregation, String> {
+        Aggregation::all()
+            .iter()
+            .find(|aggregation| aggregation.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown aggregation: {}", s))
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Where `StereoMatcher::compute` runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// OpenCL, or the CPU when no OpenCL platform is available or the
+    /// crate is built without the `opencl` feature.
+    #[default]
+    Auto,
+    /// Fails with `MatchError::Platform` without the `opencl` feature.
+    OpenCl,
+    /// Rust port of the kernels giving the same disparities bit for bit from
+    /// integer costs. The float costs of ZNCC, adaptive weights and the
+    /// guided filter, subpixel fits and confidences may differ in their last
+    /// bits on devices whose `sqrt` and `exp` round differently from the
+    /// host. Rows run in parallel with the `rayon` feature. `CostVolume` and
+    /// `DispatchStrategy` are unused.
+    Cpu,
+}
+
+impl Backend {
+    pub fn all() -> [Backend; 3] {
+        [Backend::Auto, Backend::OpenCl, Backend::Cpu]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Backend::Auto => "auto",
+            Backend::OpenCl => "opencl",
+            Backend::Cpu => "cpu",
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        Backend::all()
+            .iter()
+            .find(|backend| backend.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown backend: {}", s))
+    }
+}
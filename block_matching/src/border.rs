@@ -0,0 +1,66 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// What the left image reads as when a shifted block runs past its right edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BorderPolicy {
+    /// Repeat the last column.
+    #[default]
+    Clamp,
+    /// Reflect about the last column without repeating it.
+    Mirror,
+    /// Read the matcher's `border_value`.
+    Constant,
+    /// Skip disparities whose shifted block leaves the image; blocks with no
+    /// disparity left become invalid.
+    Invalid,
+}
+
+impl BorderPolicy {
+    pub fn all() -> [BorderPolicy; 4] {
+        [
+            BorderPolicy::Clamp,
+            BorderPolicy::Mirror,
+            BorderPolicy::Constant,
+            BorderPolicy::Invalid,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BorderPolicy::Clamp => "clamp",
+            BorderPolicy::Mirror => "mirror",
+            BorderPolicy::Constant => "constant",
+            BorderPolicy::Invalid => "invalid",
+        }
+    }
+
+    /// Value of `BORDER_POLICY` in the kernel source.
+    #[cfg(feature = "opencl")]
+    pub(crate) fn kernel_value(&self) -> usize {
+        match *self {
+            BorderPolicy::Clamp => 0,
+            BorderPolicy::Mirror => 1,
+            BorderPolicy::Constant => 2,
+            BorderPolicy::Invalid => 3,
+        }
+    }
+}
+
+impl fmt::Display for BorderPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BorderPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BorderPolicy, String> {
+        BorderPolicy::all()
+            .iter()
+            .find(|policy| policy.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown border policy: {}", s))
+    }
+}
@@ -0,0 +1,146 @@
+use image::GrayImage;
+use std::fmt;
+use std::str::FromStr;
+
+use error::{MatchError, Result};
+
+/// How sure the matcher is of each disparity, from 0 for invalid or
+/// ambiguous ones to 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfidenceMeasure {
+    /// One minus the ratio of the best cost to the best one not next to it.
+    /// ZNCC, whose costs go below zero, does not support it.
+    Ratio,
+    /// Curvature of the costs at the best one, relative to a V from the
+    /// higher of its neighbors down to zero: 1 for a sharp symmetric minimum,
+    /// at most 0.5 when a neighbor costs as little, and 0 at either end of
+    /// the searched range. ZNCC does not support it.
+    Curvature,
+    /// The best cost not next to the best one, less the best one, relative
+    /// to the spread from the best cost to the highest searched. Unlike the
+    /// ratio, it holds for ZNCC.
+    Difference,
+    /// 1 / (1 + d) for the distance d to the disparity of the left pixel
+    /// matched, which doubles the matching time and needs dense output.
+    LeftRight,
+}
+
+impl ConfidenceMeasure {
+    pub fn all() -> [ConfidenceMeasure; 4] {
+        [
+            ConfidenceMeasure::Ratio,
+            ConfidenceMeasure::Curvature,
+            ConfidenceMeasure::Difference,
+            ConfidenceMeasure::LeftRight,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ConfidenceMeasure::Ratio => "ratio",
+            ConfidenceMeasure::Curvature => "curvature",
+            ConfidenceMeasure::Difference => "difference",
+            ConfidenceMeasure::LeftRight => "left-right",
+        }
+    }
+
+    /// Value of `CONFIDENCE_MEASURE` in the kernel source. The left-right
+    /// agreement is computed after matching, so the kernels see none.
+    #[cfg(feature = "opencl")]
+    pub(crate) fn kernel_value(&self) -> usize {
+        match *self {
+            ConfidenceMeasure::LeftRight => 0,
+            ConfidenceMeasure::Ratio => 1,
+            ConfidenceMeasure::Curvature => 2,
+            ConfidenceMeasure::Difference => 3,
+        }
+    }
+
+    /// Whether the measure comes from the costs of a single match.
+    pub(crate) fn is_from_costs(&self) -> bool {
+        *self != ConfidenceMeasure::LeftRight
+    }
+
+    /// Confidence of the disparity of the smallest cost `center`, found at
+    /// index `i` of the `valid_len` searched ones; `lower` and `upper` are
+    /// the costs one disparity below and above, `second` the smallest of
+    /// those not next to it and `highest` the largest searched. Mirrors
+    /// `select_disparity` in the kernels.
+    pub(crate) fn confidence(
+        &self,
+        i: usize,
+        valid_len: usize,
+        (lower, center, upper): (f32, f32, f32),
+        (second, highest): (f32, f32),
+    ) -> f32 {
+        match *self {
+            ConfidenceMeasure::Ratio => {
+                if second > 0.0 { 1.0 - center / second } else { 0.0 }
+            }
+            ConfidenceMeasure::Curvature => {
+                let steepest = 2.0 * lower.max(upper);
+                if i > 0 && i + 1 < valid_len && steepest > 0.0 {
+                    (lower - 2.0 * center + upper) / steepest
+                } else {
+                    0.0
+                }
+            }
+            ConfidenceMeasure::Difference => {
+                let spread = highest - center;
+                if spread > 0.0 { ((second - center) / spread).min(1.0) } else { 0.0 }
+            }
+            ConfidenceMeasure::LeftRight => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for ConfidenceMeasure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ConfidenceMeasure {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<ConfidenceMeasure, String> {
+        ConfidenceMeasure::all()
+            .iter()
+            .find(|measure| measure.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown confidence measure: {}", s))
+    }
+}
+
+/// Confidence of every disparity of a `DisparityMap`, in the same layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfidenceMap {
+    pub width: usize,
+    pub height: usize,
+    pub measure: ConfidenceMeasure,
+    pub data: Vec<f32>,
+}
+
+impl ConfidenceMap {
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+
+    /// Confidences scaled from 0..1 to black through white.
+    pub fn to_gray_image(&self) -> Result<GrayImage> {
+        if self.data.len() != self.width * self.height {
+            return Err(MatchError::InvalidParameter(format!(
+                "{} confidences do not fill a {}x{} map",
+                self.data.len(), self.width, self.height
+            )));
+        }
+        let pixels = self.data.iter().map(|&confidence| (confidence * 255.0).round() as u8).collect();
+        Ok(GrayImage::from_raw(self.width as u32, self.height as u32, pixels).unwrap())
+    }
+
+    pub fn save(&self, file_name: &str) -> Result<()> {
+        self.to_gray_image()?
+            .save(file_name)
+            .map_err(|error| MatchError::Output { file_name: file_name.to_string(), error })
+    }
+}
@@ -0,0 +1,42 @@
+use confidence::{ConfidenceMap, ConfidenceMeasure};
+use disparity::DisparityMap;
+
+/// Distance from the disparity of `right` at (`x`, `y`) to that of the left
+/// pixel it matches, as read from `mirrored_left`, the disparities of
+/// `StereoPair::mirrored`. `None` when either is invalid.
+fn left_right_difference(right: &DisparityMap, mirrored_left: &DisparityMap, x: usize, y: usize) -> Option<f32> {
+    let w = right.width;
+    let disparity = right.get(x, y)?;
+    let left_x = x + disparity.round() as usize;
+    if left_x >= w {
+        return None;
+    }
+    mirrored_left.get(w - 1 - left_x, y).map(|left_disparity| (left_disparity - disparity).abs())
+}
+
+/// Marks a disparity of `right` invalid unless the left pixel it matches has
+/// a disparity within `max_difference` of it. Pixels hidden from the left
+/// camera fail, since their match belongs to the occluding surface.
+pub(crate) fn check_left_right(right: &mut DisparityMap, mirrored_left: &DisparityMap, max_difference: f32) {
+    for y in 0..right.height {
+        for x in 0..right.width {
+            let consistent = left_right_difference(right, mirrored_left, x, y)
+                .is_some_and(|difference| difference <= max_difference);
+            if !consistent {
+                right.invalidate(x, y);
+            }
+        }
+    }
+}
+
+/// `ConfidenceMeasure::LeftRight` of every disparity of `right`: 1 where the
+/// left pixel it matches agrees exactly, falling toward 0 as they differ.
+pub(crate) fn left_right_agreement(right: &DisparityMap, mirrored_left: &DisparityMap) -> ConfidenceMap {
+    let data = (0..right.width * right.height)
+        .map(|i| match left_right_difference(right, mirrored_left, i % right.width, i / right.width) {
+            Some(difference) => 1.0 / (1.0 + difference),
+            None => 0.0,
+        })
+        .collect();
+    ConfidenceMap { width: right.width, height: right.height, measure: ConfidenceMeasure::LeftRight, data }
+}
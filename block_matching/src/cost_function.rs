@@ -0,0 +1,92 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How well a right block matches a shifted left block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CostFunction {
+    /// Absolute difference, making block costs a SAD.
+    #[default]
+    Sad,
+    /// Squared difference, making block costs an SSD. Suits low-noise images.
+    Ssd,
+    /// Zero-mean normalized cross-correlation of the blocks, highest wins.
+    /// Unaffected by gain and offset differences between the cameras. Blocks
+    /// of uniform gray in the right image are invalid.
+    Zncc,
+    /// Hamming distance between census transforms over the matcher's
+    /// `transform_window`. Robust to radiometric differences.
+    Census,
+    /// Absolute difference between rank transforms over the matcher's
+    /// `transform_window`, each pixel replaced by the count of darker pixels
+    /// around it. A cheaper non-parametric cost than census.
+    Rank,
+    /// Birchfield-Tomasi dissimilarity in half gray levels, comparing each
+    /// pixel with the other image interpolated half a pixel around the match.
+    /// Insensitive to sampling, so slanted surfaces alias less.
+    BirchfieldTomasi,
+}
+
+impl CostFunction {
+    pub fn all() -> [CostFunction; 6] {
+        [
+            CostFunction::Sad,
+            CostFunction::Ssd,
+            CostFunction::Zncc,
+            CostFunction::Census,
+            CostFunction::Rank,
+            CostFunction::BirchfieldTomasi,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CostFunction::Sad => "sad",
+            CostFunction::Ssd => "ssd",
+            CostFunction::Zncc => "zncc",
+            CostFunction::Census => "census",
+            CostFunction::Rank => "rank",
+            CostFunction::BirchfieldTomasi => "bt",
+        }
+    }
+
+    /// Value of `COST_FUNCTION` in the kernel source.
+    #[cfg(feature = "opencl")]
+    pub(crate) fn kernel_value(&self) -> usize {
+        match *self {
+            CostFunction::Sad => 0,
+            CostFunction::Ssd => 1,
+            CostFunction::Zncc => 2,
+            CostFunction::Census => 3,
+            CostFunction::Rank => 4,
+            CostFunction::BirchfieldTomasi => 5,
+        }
+    }
+
+    /// Largest per-pixel term summed over a block.
+    pub(crate) fn max_pixel_cost(&self) -> u64 {
+        match *self {
+            CostFunction::Sad | CostFunction::Rank => 255,
+            CostFunction::Ssd | CostFunction::Zncc => 255 * 255,
+            CostFunction::Census => 64,
+            CostFunction::BirchfieldTomasi => 2 * 255,
+        }
+    }
+}
+
+impl fmt::Display for CostFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CostFunction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CostFunction, String> {
+        CostFunction::all()
+            .iter()
+            .find(|function| function.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown cost function: {}", s))
+    }
+}
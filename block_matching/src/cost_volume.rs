@@ -0,0 +1,52 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether the per-pixel costs of every disparity are stored before the
+/// blocks are summed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CostVolume {
+    /// Stored unless the `width * height * diff_len` volume, or the row costs
+    /// of `Aggregation::Cross`, exceeds the device's
+    /// `CL_DEVICE_MAX_MEM_ALLOC_SIZE`. Aggregations that need it
+    /// stored fail with `MatchError::InvalidParameter` when their buffers
+    /// exceed it.
+    #[default]
+    Auto,
+    /// Filled by `get_diffs` as chosen by the `DispatchStrategy`.
+    Stored,
+    /// Not stored; block costs are summed from the pixels directly and the
+    /// `DispatchStrategy` is unused.
+    Streamed,
+}
+
+impl CostVolume {
+    pub fn all() -> [CostVolume; 3] {
+        [CostVolume::Auto, CostVolume::Stored, CostVolume::Streamed]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CostVolume::Auto => "auto",
+            CostVolume::Stored => "stored",
+            CostVolume::Streamed => "streamed",
+        }
+    }
+}
+
+impl fmt::Display for CostVolume {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CostVolume {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CostVolume, String> {
+        CostVolume::all()
+            .iter()
+            .find(|volume| volume.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown cost volume: {}", s))
+    }
+}
@@ -0,0 +1,958 @@
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use aggregation::Aggregation;
+use border::BorderPolicy;
+use confidence::ConfidenceMap;
+use cost_function::CostFunction;
+use density::Density;
+use disparity::{Disparity, DisparityMap};
+use matcher::StereoMatcher;
+use pair::StereoPair;
+use subpixel::SubpixelFit;
+
+/// Costs around the smallest of those seen so far, as the kernels track
+/// them: one disparity below and above it, and the smallest of the
+/// disparities not next to it.
+struct NeighborCosts {
+    lower: f32,
+    upper: f32,
+    second: f32,
+    highest: f32,
+    /// Last cost seen, and the smallest of those before it.
+    previous: f32,
+    early: f32,
+}
+
+impl NeighborCosts {
+    fn new() -> NeighborCosts {
+        NeighborCosts {
+            lower: 0.0,
+            upper: 0.0,
+            second: f32::INFINITY,
+            highest: f32::NEG_INFINITY,
+            previous: 0.0,
+            early: f32::INFINITY,
+        }
+    }
+
+    /// Feeds the cost of index `i`, after `min_index` was moved to the
+    /// smallest cost so far.
+    fn track(&mut self, i: usize, min_index: usize, cost: f32) {
+        if i == min_index {
+            self.lower = self.previous;
+            self.second = self.early;
+        } else if i == min_index + 1 {
+            self.upper = cost;
+        } else if self.second > cost {
+            self.second = cost;
+        }
+        if i > 0 && self.early > self.previous {
+            self.early = self.previous;
+        }
+        self.previous = cost;
+        self.highest = self.highest.max(cost);
+    }
+}
+
+/// Same steps as the kernels, with every cost computed on the fly.
+struct Search<'a> {
+    matcher: &'a StereoMatcher,
+    pair: &'a StereoPair,
+    diff_len: usize,
+    /// Pixels compared: gray levels, or ranks for `CostFunction::Rank`.
+    left: &'a [u8],
+    right: &'a [u8],
+    /// Census bit strings of the left and right images for
+    /// `CostFunction::Census`.
+    census: Option<(Vec<u64>, Vec<u64>)>,
+    /// Colors for `Aggregation::AdaptiveWeights` with `channels` values per
+    /// pixel.
+    left_colors: &'a [u8],
+    right_colors: &'a [u8],
+    channels: usize,
+    /// Window statistics for `CostFunction::Zncc`.
+    window_sums: Option<WindowSums>,
+}
+
+/// Integral images of the left image as matching reads it, up to every
+/// searched disparity past its right edge, of the right image, and of their
+/// squares.
+struct WindowSums {
+    left_w: usize,
+    left: Vec<i64>,
+    left_square: Vec<i64>,
+    right: Vec<i64>,
+    right_square: Vec<i64>,
+}
+
+impl<'a> Search<'a> {
+    /// Index of the left pixel read at (`x`, `y`), or `None` for the
+    /// constant border.
+    fn get_left_index(&self, x: usize, y: usize) -> Option<usize> {
+        let w = self.pair.width;
+        let x = if x < w {
+            x
+        } else {
+            match self.matcher.border {
+                BorderPolicy::Mirror => (2 * (w - 1)).saturating_sub(x),
+                BorderPolicy::Constant => return None,
+                BorderPolicy::Clamp | BorderPolicy::Invalid => w - 1,
+            }
+        };
+        Some(y * w + x)
+    }
+
+    fn get_left_pixel(&self, x: usize, y: usize) -> u8 {
+        match self.get_left_index(x, y) {
+            Some(i) => self.left[i],
+            // No pixel of a constant region is darker than its center.
+            None if self.matcher.cost_function == CostFunction::Rank => 0,
+            None => self.matcher.border_value,
+        }
+    }
+
+    fn get_diff(&self, x: usize, y: usize, disparity: usize) -> u16 {
+        let right_index = y * self.pair.width + x;
+        if let Some((ref left_census, ref right_census)) = self.census {
+            // No pixel of a constant region is darker than its center.
+            let left = self.get_left_index(x + disparity, y).map_or(0, |i| left_census[i]);
+            return (left ^ right_census[right_index]).count_ones() as u16;
+        }
+        let left = self.get_left_pixel(x + disparity, y);
+        let right = self.right[right_index];
+        let diff = u16::from(left.abs_diff(right));
+        match self.matcher.cost_function {
+            CostFunction::Sad | CostFunction::Rank => diff,
+            CostFunction::Ssd => diff * diff,
+            CostFunction::Zncc => u16::from(left) * u16::from(right),
+            CostFunction::Census => unreachable!(),
+            CostFunction::BirchfieldTomasi => self.get_birchfield_tomasi(x, y, disparity),
+        }
+    }
+
+    /// In half gray levels, as in the kernel.
+    fn get_birchfield_tomasi(&self, x: usize, y: usize, disparity: usize) -> u16 {
+        let w = self.pair.width;
+        let left_x = x + disparity;
+        let left = i32::from(self.get_left_pixel(left_x, y));
+        let right = i32::from(self.right[y * w + x]);
+        let left_minus = left + i32::from(self.get_left_pixel(left_x.saturating_sub(1), y));
+        let left_plus = left + i32::from(self.get_left_pixel(left_x + 1, y));
+        let right_minus = right + i32::from(self.right[y * w + x.saturating_sub(1)]);
+        let right_plus = right + i32::from(self.right[y * w + (x + 1).min(w - 1)]);
+        let (left_min, left_max) = min_max(2 * left, left_minus, left_plus);
+        let (right_min, right_max) = min_max(2 * right, right_minus, right_plus);
+        let right_to_left = 0.max(2 * right - left_max).max(left_min - 2 * right);
+        let left_to_right = 0.max(2 * left - right_max).max(right_min - 2 * left);
+        right_to_left.min(left_to_right) as u16
+    }
+
+    fn get_window_sums(&self) -> WindowSums {
+        let (w, h) = (self.pair.width, self.pair.height);
+        let left_w = w + self.matcher.min_disparity + self.diff_len;
+        let left = |x, y| i64::from(self.get_left_pixel(x, y));
+        let right = |x, y| i64::from(self.right[y * w + x]);
+        WindowSums {
+            left_w,
+            left: integral_image(left_w, h, left),
+            left_square: integral_image(left_w, h, |x, y| left(x, y) * left(x, y)),
+            right: integral_image(w, h, right),
+            right_square: integral_image(w, h, |x, y| right(x, y) * right(x, y)),
+        }
+    }
+
+    /// Integer costs and the f32 ZNCC both convert to f64 exactly, so
+    /// comparisons match the kernels.
+    fn get_window_cost(&self, x_begin: usize, x_end: usize, y_begin: usize, y_end: usize, disparity: usize) -> f64 {
+        let mut diff_point = 0u32;
+        for x in x_begin..x_end {
+            for y in y_begin..y_end {
+                diff_point += u32::from(self.get_diff(x, y, disparity));
+            }
+        }
+        let sums = match self.window_sums {
+            Some(ref sums) => sums,
+            None => return f64::from(diff_point),
+        };
+        let w = self.pair.width;
+        let left_block = (x_begin + disparity, x_end + disparity, y_begin, y_end);
+        let right_block = (x_begin, x_end, y_begin, y_end);
+        let left_sum = box_sum(&sums.left, sums.left_w, left_block);
+        let left_square_sum = box_sum(&sums.left_square, sums.left_w, left_block);
+        let right_sum = box_sum(&sums.right, w, right_block);
+        let right_square_sum = box_sum(&sums.right_square, w, right_block);
+        let n = ((x_end - x_begin) * (y_end - y_begin)) as i64;
+        let covariance = n * i64::from(diff_point) - left_sum * right_sum;
+        let left_variance = n * left_square_sum - left_sum * left_sum;
+        let right_variance = n * right_square_sum - right_sum * right_sum;
+        // A flat window correlates with nothing, the worst cost.
+        if left_variance == 0 || right_variance == 0 {
+            return 1.0;
+        }
+        f64::from(-(covariance as f32) / (left_variance as f32 * right_variance as f32).sqrt())
+    }
+
+    fn find_disparity<T: Disparity>(
+        &self,
+        x_begin: usize,
+        x_end: usize,
+        y_begin: usize,
+        y_end: usize,
+    ) -> (T, f32) {
+        let w = self.pair.width;
+        let min_disparity = self.matcher.min_disparity;
+        let mut valid_len = self.diff_len;
+        if self.matcher.border == BorderPolicy::Invalid {
+            if x_end + min_disparity > w {
+                return (T::INVALID, 0.0);
+            }
+            valid_len = valid_len.min(w - x_end - min_disparity + 1);
+        }
+        if let Some(ref sums) = self.window_sums {
+            // No disparity matches a flat right window.
+            let block = (x_begin, x_end, y_begin, y_end);
+            let n = ((x_end - x_begin) * (y_end - y_begin)) as i64;
+            let sum = box_sum(&sums.right, w, block);
+            if n * box_sum(&sums.right_square, w, block) == sum * sum {
+                return (T::INVALID, 0.0);
+            }
+        }
+        let mut min_diff_index = 0;
+        let mut min_diff_point = 0.0;
+        let mut neighbors = NeighborCosts::new();
+        for i in 0..valid_len {
+            let diff_point = self.get_window_cost(x_begin, x_end, y_begin, y_end, min_disparity + i);
+            if i == 0 || min_diff_point > diff_point {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            neighbors.track(i, min_diff_index, diff_point as f32);
+        }
+        self.select_disparity(min_diff_index, valid_len, min_diff_point as f32, &neighbors)
+    }
+
+    /// Disparity of the smallest cost `center`, found at index `i` of the
+    /// `valid_len` searched ones, refined by the `SubpixelFit`, or invalid
+    /// when it is not unique enough. The confidence is 0 unless measured
+    /// from the costs.
+    fn select_disparity<T: Disparity>(
+        &self,
+        i: usize,
+        valid_len: usize,
+        center: f32,
+        neighbors: &NeighborCosts,
+    ) -> (T, f32) {
+        let uniqueness_ratio = self.matcher.uniqueness_ratio;
+        let second = neighbors.second;
+        if uniqueness_ratio > 0 && (second <= center || (second - center) * 100.0 < center * uniqueness_ratio as f32) {
+            return (T::INVALID, 0.0);
+        }
+        let confidence = match self.matcher.confidence {
+            Some(measure) => measure.confidence(
+                i,
+                valid_len,
+                (neighbors.lower, center, neighbors.upper),
+                (neighbors.second, neighbors.highest),
+            ),
+            None => 0.0,
+        };
+        let disparity = self.matcher.min_disparity + i;
+        let fit = self.matcher.subpixel_fit;
+        if fit == SubpixelFit::None {
+            return (T::from_disparity(disparity), confidence);
+        }
+        let offset = if i > 0 && i + 1 < valid_len { fit.offset(neighbors.lower, center, neighbors.upper) } else { 0.0 };
+        (T::from_subpixel(disparity as f32 + offset), confidence)
+    }
+
+    /// Whether the right image varies less than the texture threshold over
+    /// the block of the result at (`result_x`, `result_y`).
+    fn is_textureless(&self, result_x: usize, result_y: usize) -> bool {
+        let (block_w, block_h) = (self.matcher.block_w, self.matcher.block_h);
+        let (x_begin, x_end, y_begin, y_end) = match self.matcher.density {
+            Density::Dense => self.centered_block(result_x, result_y),
+            Density::Tiled => (
+                result_x * block_w, (result_x + 1) * block_w, result_y * block_h, (result_y + 1) * block_h,
+            ),
+        };
+        let (mut sum, mut square_sum) = (0u64, 0u64);
+        for x in x_begin..x_end {
+            for y in y_begin..y_end {
+                let pixel = u64::from(self.pair.right[y * self.pair.width + x]);
+                sum += pixel;
+                square_sum += pixel * pixel;
+            }
+        }
+        let n = ((x_end - x_begin) * (y_end - y_begin)) as u64;
+        n * square_sum - sum * sum < u64::from(self.matcher.texture_threshold) * n * n
+    }
+
+    /// `(x_begin, x_end, y_begin, y_end)` of the block centered on (`x`,
+    /// `y`), clipped where it leaves the image.
+    fn centered_block(&self, x: usize, y: usize) -> (usize, usize, usize, usize) {
+        let block_w = self.matcher.block_w;
+        let block_h = self.matcher.block_h;
+        let x_end = (x + block_w - block_w / 2).min(self.pair.width);
+        let y_end = (y + block_h - block_h / 2).min(self.pair.height);
+        (x.saturating_sub(block_w / 2), x_end, y.saturating_sub(block_h / 2), y_end)
+    }
+
+    fn find_centered_disparity<T: Disparity>(&self, x: usize, y: usize) -> (T, f32) {
+        let (x_begin, x_end, y_begin, y_end) = self.centered_block(x, y);
+        self.find_disparity(x_begin, x_end, y_begin, y_end)
+    }
+
+    fn get_left_color_index(&self, x: usize, y: usize) -> usize {
+        let w = self.pair.width;
+        match self.matcher.border {
+            BorderPolicy::Mirror if x >= w => y * w + (2 * (w - 1)).saturating_sub(x),
+            _ => y * w + x.min(w - 1),
+        }
+    }
+
+    fn get_support_weight(&self, colors: &[u8], a: usize, b: usize, spatial_distance: f32) -> f32 {
+        let (gamma_color, gamma_proximity) = self.matcher.adaptive_weights;
+        let channels = self.channels;
+        let square_sum: u32 = colors[a * channels..][..channels]
+            .iter()
+            .zip(&colors[b * channels..][..channels])
+            .map(|(&first, &second)| u32::from(first.abs_diff(second)).pow(2))
+            .sum();
+        (-((square_sum as f32).sqrt() / gamma_color + spatial_distance / gamma_proximity)).exp()
+    }
+
+    fn find_adaptive_disparity<T: Disparity>(&self, x: usize, y: usize) -> (T, f32) {
+        let w = self.pair.width;
+        let min_disparity = self.matcher.min_disparity;
+        let (x_begin, x_end, y_begin, y_end) = self.centered_block(x, y);
+        let mut valid_len = self.diff_len;
+        if self.matcher.border == BorderPolicy::Invalid {
+            if x_end + min_disparity > w {
+                return (T::INVALID, 0.0);
+            }
+            valid_len = valid_len.min(w - x_end - min_disparity + 1);
+        }
+        let mut min_diff_index = 0;
+        let mut min_diff_point = 0.0;
+        let mut neighbors = NeighborCosts::new();
+        for i in 0..valid_len {
+            let disparity = min_disparity + i;
+            let left_center = self.get_left_color_index(x + disparity, y);
+            let mut weighted_sum = 0.0f32;
+            let mut weight_sum = 0.0f32;
+            for block_x in x_begin..x_end {
+                for block_y in y_begin..y_end {
+                    let dx = block_x.abs_diff(x);
+                    let dy = block_y.abs_diff(y);
+                    let spatial_distance = ((dx * dx + dy * dy) as f32).sqrt();
+                    let mut weight =
+                        self.get_support_weight(self.right_colors, y * w + x, block_y * w + block_x, spatial_distance);
+                    weight *= self.get_support_weight(
+                        self.left_colors,
+                        left_center,
+                        self.get_left_color_index(block_x + disparity, block_y),
+                        spatial_distance,
+                    );
+                    weighted_sum += weight * f32::from(self.get_diff(block_x, block_y, disparity));
+                    weight_sum += weight;
+                }
+            }
+            let diff_point = weighted_sum / weight_sum;
+            if i == 0 || min_diff_point > diff_point {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            neighbors.track(i, min_diff_index, diff_point);
+        }
+        self.select_disparity(min_diff_index, valid_len, min_diff_point, &neighbors)
+    }
+
+    fn colors_are_similar(&self, a: usize, b: usize) -> bool {
+        let channels = self.channels;
+        let threshold = self.matcher.cross_arms.0;
+        self.right_colors[a * channels..][..channels]
+            .iter()
+            .zip(&self.right_colors[b * channels..][..channels])
+            .all(|(&first, &second)| first.abs_diff(second) < threshold)
+    }
+
+    /// Arm lengths of the cross at every right pixel, left, right, up and
+    /// down.
+    fn cross_arms(&self) -> Vec<[usize; 4]> {
+        let w = self.pair.width;
+        let h = self.pair.height;
+        let max_arm_length = self.matcher.cross_arms.1;
+        let arm = |center: usize, limit: usize, step: &dyn Fn(usize) -> usize| {
+            (0..max_arm_length.min(limit))
+                .take_while(|&n| self.colors_are_similar(center, step(n + 1)))
+                .count()
+        };
+        (0..w * h)
+            .map(|center| {
+                let (x, y) = (center % w, center / w);
+                [
+                    arm(center, x, &|n| center - n),
+                    arm(center, w - 1 - x, &|n| center + n),
+                    arm(center, y, &|n| center - n * w),
+                    arm(center, h - 1 - y, &|n| center + n * w),
+                ]
+            })
+            .collect()
+    }
+
+    /// Every cost summed along its pixel's horizontal arm, in the cost
+    /// volume layout of the kernels.
+    fn get_cross_row_costs(&self, arms: &[[usize; 4]]) -> Vec<u32> {
+        let w = self.pair.width;
+        let mut row_costs = vec![0u32; w * self.pair.height * self.diff_len];
+        let fill_costs = |(center, costs): (usize, &mut [u32])| {
+            let (x, y) = (center % w, center / w);
+            for (i, cost) in costs.iter_mut().enumerate() {
+                *cost = (x - arms[center][0]..=x + arms[center][1])
+                    .map(|row_x| u32::from(self.get_diff(row_x, y, self.matcher.min_disparity + i)))
+                    .sum();
+            }
+        };
+        #[cfg(feature = "rayon")]
+        row_costs.par_chunks_mut(self.diff_len).enumerate().for_each(fill_costs);
+        #[cfg(not(feature = "rayon"))]
+        row_costs.chunks_mut(self.diff_len).enumerate().for_each(fill_costs);
+        row_costs
+    }
+
+    fn find_cross_disparity<T: Disparity>(
+        &self,
+        arms: &[[usize; 4]],
+        row_costs: &[u32],
+        x: usize,
+        y: usize,
+    ) -> (T, f32) {
+        let w = self.pair.width;
+        let min_disparity = self.matcher.min_disparity;
+        let region_ys = y - arms[y * w + x][2]..y + arms[y * w + x][3] + 1;
+        let mut valid_len = self.diff_len;
+        if self.matcher.border == BorderPolicy::Invalid {
+            // The widest row of the region decides, as for a block.
+            let x_end = region_ys.clone().map(|region_y| x + arms[region_y * w + x][1] + 1).max().unwrap();
+            if x_end + min_disparity > w {
+                return (T::INVALID, 0.0);
+            }
+            valid_len = valid_len.min(w - x_end - min_disparity + 1);
+        }
+        let mut min_diff_index = 0;
+        let mut min_diff_point = 0;
+        let mut neighbors = NeighborCosts::new();
+        for i in 0..valid_len {
+            let diff_point: u32 = region_ys
+                .clone()
+                .map(|region_y| row_costs[(region_y * w + x) * self.diff_len + i])
+                .sum();
+            if i == 0 || min_diff_point > diff_point {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            neighbors.track(i, min_diff_index, diff_point as f32);
+        }
+        self.select_disparity(min_diff_index, valid_len, min_diff_point as f32, &neighbors)
+    }
+
+    /// Integral image of every cost slice, in the cost volume layout of the
+    /// kernels: at each pixel, the costs above and left of it summed with
+    /// wrapping, as uints wrap on devices.
+    fn get_integrals(&self) -> Vec<u32> {
+        let w = self.pair.width;
+        let row_len = w * self.diff_len;
+        let mut integrals = vec![0u32; row_len * self.pair.height];
+        let fill_row = |(y, row): (usize, &mut [u32])| {
+            for x in 0..w {
+                for i in 0..self.diff_len {
+                    let diff = u32::from(self.get_diff(x, y, self.matcher.min_disparity + i));
+                    let previous = if x == 0 { 0 } else { row[(x - 1) * self.diff_len + i] };
+                    row[x * self.diff_len + i] = previous.wrapping_add(diff);
+                }
+            }
+        };
+        #[cfg(feature = "rayon")]
+        integrals.par_chunks_mut(row_len).enumerate().for_each(fill_row);
+        #[cfg(not(feature = "rayon"))]
+        integrals.chunks_mut(row_len).enumerate().for_each(fill_row);
+        for index in row_len..integrals.len() {
+            integrals[index] = integrals[index].wrapping_add(integrals[index - row_len]);
+        }
+        integrals
+    }
+
+    fn find_integral_disparity<T: Disparity>(
+        &self,
+        integrals: &[u32],
+        (x_begin, x_end, y_begin, y_end): (usize, usize, usize, usize),
+    ) -> (T, f32) {
+        let w = self.pair.width;
+        let min_disparity = self.matcher.min_disparity;
+        let mut valid_len = self.diff_len;
+        if self.matcher.border == BorderPolicy::Invalid {
+            if x_end + min_disparity > w {
+                return (T::INVALID, 0.0);
+            }
+            valid_len = valid_len.min(w - x_end - min_disparity + 1);
+        }
+        let get_integral = |x_end: usize, y_end: usize, i: usize| {
+            if x_end == 0 || y_end == 0 {
+                0
+            } else {
+                integrals[((y_end - 1) * w + x_end - 1) * self.diff_len + i]
+            }
+        };
+        let mut min_diff_index = 0;
+        let mut min_diff_point = 0;
+        let mut neighbors = NeighborCosts::new();
+        for i in 0..valid_len {
+            let diff_point = get_integral(x_end, y_end, i)
+                .wrapping_sub(get_integral(x_begin, y_end, i))
+                .wrapping_sub(get_integral(x_end, y_begin, i))
+                .wrapping_add(get_integral(x_begin, y_begin, i));
+            if i == 0 || min_diff_point > diff_point {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            neighbors.track(i, min_diff_index, diff_point as f32);
+        }
+        self.select_disparity(min_diff_index, valid_len, min_diff_point as f32, &neighbors)
+    }
+
+    /// Sums of the guided filter coefficients a and b of every cost slice
+    /// along the row of each block, one slice after another. Window sums of
+    /// the costs come from integral images, so they take the same time
+    /// whatever the block size and stay the kernels' exact integers.
+    fn get_guided_row_sums(&self) -> Vec<(f32, f32)> {
+        let w = self.pair.width;
+        let h = self.pair.height;
+        let guide = &self.pair.right;
+        let epsilon = self.matcher.guided_filter_epsilon;
+        let guide_integral = integral_image(w, h, |x, y| i64::from(guide[y * w + x]));
+        let guide_square_integral = integral_image(w, h, |x, y| i64::from(guide[y * w + x]).pow(2));
+        let mut row_sums = vec![(0.0, 0.0); w * h * self.diff_len];
+        let fill_slice = |(i, row_sums): (usize, &mut [(f32, f32)])| {
+            let costs: Vec<i64> = (0..w * h)
+                .map(|index| i64::from(self.get_diff(index % w, index / w, self.matcher.min_disparity + i)))
+                .collect();
+            let cost_integral = integral_image(w, h, |x, y| costs[y * w + x]);
+            let guided_cost_integral = integral_image(w, h, |x, y| i64::from(guide[y * w + x]) * costs[y * w + x]);
+            let mut coefficients = vec![(0.0, 0.0); w * h];
+            for (center, coefficient) in coefficients.iter_mut().enumerate() {
+                let block = self.centered_block(center % w, center / w);
+                let (x_begin, x_end, y_begin, y_end) = block;
+                let n = ((x_end - x_begin) * (y_end - y_begin)) as i64;
+                let guide_sum = box_sum(&guide_integral, w, block);
+                let guide_square_sum = box_sum(&guide_square_integral, w, block);
+                let cost_sum = box_sum(&cost_integral, w, block);
+                let guided_cost_sum = box_sum(&guided_cost_integral, w, block);
+                let variance = (n * guide_square_sum - guide_sum * guide_sum) as f32 / (n * n) as f32;
+                let covariance = (n * guided_cost_sum - guide_sum * cost_sum) as f32 / (n * n) as f32;
+                let a = covariance / (variance + epsilon);
+                *coefficient = (a, cost_sum as f32 / n as f32 - a * (guide_sum as f32 / n as f32));
+            }
+            for (center, row_sum) in row_sums.iter_mut().enumerate() {
+                let (x_begin, x_end, _, _) = self.centered_block(center % w, center / w);
+                let row = center / w * w;
+                *row_sum = coefficients[row + x_begin..row + x_end]
+                    .iter()
+                    .fold((0.0, 0.0), |(a_sum, b_sum), &(a, b)| (a_sum + a, b_sum + b));
+            }
+        };
+        #[cfg(feature = "rayon")]
+        row_sums.par_chunks_mut(w * h).enumerate().for_each(fill_slice);
+        #[cfg(not(feature = "rayon"))]
+        row_sums.chunks_mut(w * h).enumerate().for_each(fill_slice);
+        row_sums
+    }
+
+    /// Adds the row sums down the block, in time linear in its width plus
+    /// its height.
+    fn find_guided_disparity<T: Disparity>(&self, row_sums: &[(f32, f32)], x: usize, y: usize) -> (T, f32) {
+        let w = self.pair.width;
+        let min_disparity = self.matcher.min_disparity;
+        let (x_begin, x_end, y_begin, y_end) = self.centered_block(x, y);
+        let mut valid_len = self.diff_len;
+        if self.matcher.border == BorderPolicy::Invalid {
+            if x_end + min_disparity > w {
+                return (T::INVALID, 0.0);
+            }
+            valid_len = valid_len.min(w - x_end - min_disparity + 1);
+        }
+        let n = ((x_end - x_begin) * (y_end - y_begin)) as f32;
+        let guide = f32::from(self.pair.right[y * w + x]);
+        let mut min_diff_index = 0;
+        let mut min_diff_point = 0.0;
+        let mut neighbors = NeighborCosts::new();
+        for i in 0..valid_len {
+            let (mut a_sum, mut b_sum) = (0.0f32, 0.0f32);
+            for block_y in y_begin..y_end {
+                let (a, b) = row_sums[(i * self.pair.height + block_y) * w + x];
+                a_sum += a;
+                b_sum += b;
+            }
+            let diff_point = a_sum / n * guide + b_sum / n;
+            if i == 0 || min_diff_point > diff_point {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            neighbors.track(i, min_diff_index, diff_point);
+        }
+        self.select_disparity(min_diff_index, valid_len, min_diff_point, &neighbors)
+    }
+
+    /// Sum of the SGM path costs over every direction, in the cost volume
+    /// layout of the kernels.
+    fn aggregate_sgm(&self) -> Vec<u32> {
+        let w = self.pair.width;
+        let h = self.pair.height;
+        let diff_len = self.diff_len;
+        let (p1, p2) = self.matcher.sgm_penalties;
+        let mut diffs = vec![0u16; w * h * diff_len];
+        let fill_costs = |(index, costs): (usize, &mut [u16])| {
+            for (i, cost) in costs.iter_mut().enumerate() {
+                *cost = self.get_diff(index % w, index / w, self.matcher.min_disparity + i);
+            }
+        };
+        #[cfg(feature = "rayon")]
+        diffs.par_chunks_mut(diff_len).enumerate().for_each(fill_costs);
+        #[cfg(not(feature = "rayon"))]
+        diffs.chunks_mut(diff_len).enumerate().for_each(fill_costs);
+
+        let inside = |x: i64, y: i64| x >= 0 && x < w as i64 && y >= 0 && y < h as i64;
+        let mut aggregated = vec![0u32; w * h * diff_len];
+        let mut path_costs = vec![0u32; diff_len];
+        for &(dx, dy) in self.matcher.sgm_paths.directions() {
+            let (dx, dy) = (i64::from(dx), i64::from(dy));
+            for start in 0..w * h {
+                let (mut x, mut y) = ((start % w) as i64, (start / w) as i64);
+                if inside(x - dx, y - dy) {
+                    continue;
+                }
+                path_costs.iter_mut().for_each(|cost| *cost = 0);
+                let mut previous_min = 0;
+                while inside(x, y) {
+                    let index = (y as usize * w + x as usize) * diff_len;
+                    let mut lower_previous = 0;
+                    let mut current_min = 0;
+                    for i in 0..diff_len {
+                        let previous = path_costs[i];
+                        let mut cost = previous.min(previous_min + p2);
+                        if i > 0 {
+                            cost = cost.min(lower_previous + p1);
+                        }
+                        if i + 1 < diff_len {
+                            cost = cost.min(path_costs[i + 1] + p1);
+                        }
+                        lower_previous = previous;
+                        path_costs[i] = u32::from(diffs[index + i]) + cost - previous_min;
+                        aggregated[index + i] += path_costs[i];
+                        if i == 0 || current_min > path_costs[i] {
+                            current_min = path_costs[i];
+                        }
+                    }
+                    previous_min = current_min;
+                    x += dx;
+                    y += dy;
+                }
+            }
+        }
+        aggregated
+    }
+
+    fn find_sgm_disparity<T: Disparity>(&self, aggregated: &[u32], x: usize, y: usize) -> (T, f32) {
+        let w = self.pair.width;
+        let min_disparity = self.matcher.min_disparity;
+        let mut valid_len = self.diff_len;
+        if self.matcher.border == BorderPolicy::Invalid {
+            if x + 1 + min_disparity > w {
+                return (T::INVALID, 0.0);
+            }
+            valid_len = valid_len.min(w - x - min_disparity);
+        }
+        let costs = &aggregated[(y * w + x) * self.diff_len..][..valid_len];
+        let mut min_diff_index = 0;
+        let mut neighbors = NeighborCosts::new();
+        for (i, &cost) in costs.iter().enumerate() {
+            if costs[min_diff_index] > cost {
+                min_diff_index = i;
+            }
+            neighbors.track(i, min_diff_index, cost as f32);
+        }
+        self.select_disparity(min_diff_index, valid_len, costs[min_diff_index] as f32, &neighbors)
+    }
+}
+
+/// Integral image of `value` over a `w` x `h` image, with a zero first row
+/// and column.
+fn integral_image<F: Fn(usize, usize) -> i64>(w: usize, h: usize, value: F) -> Vec<i64> {
+    let mut integral = vec![0; (w + 1) * (h + 1)];
+    for y in 0..h {
+        let mut row_sum = 0;
+        for x in 0..w {
+            row_sum += value(x, y);
+            integral[(y + 1) * (w + 1) + x + 1] = integral[y * (w + 1) + x + 1] + row_sum;
+        }
+    }
+    integral
+}
+
+/// Sum over a block given as `(x_begin, x_end, y_begin, y_end)` of the
+/// values `integral` was taken of.
+fn box_sum(integral: &[i64], w: usize, (x_begin, x_end, y_begin, y_end): (usize, usize, usize, usize)) -> i64 {
+    let at = |x: usize, y: usize| integral[y * (w + 1) + x];
+    at(x_end, y_end) - at(x_begin, y_end) - at(x_end, y_begin) + at(x_begin, y_begin)
+}
+
+fn min_max(a: i32, b: i32, c: i32) -> (i32, i32) {
+    (a.min(b).min(c), a.max(b).max(c))
+}
+
+/// Folds whether each pixel of the window centered on every pixel is darker
+/// than the center, in the order of the transform kernels.
+fn transform<F>(pixels: &[u8], w: usize, h: usize, (window_w, window_h): (usize, usize), fold: F) -> Vec<u64>
+where
+    F: Fn(u64, bool) -> u64,
+{
+    let mut transformed = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let center = pixels[y * w + x];
+            let mut value = 0u64;
+            for window_y in 0..window_h {
+                for window_x in 0..window_w {
+                    if window_x == window_w / 2 && window_y == window_h / 2 {
+                        continue;
+                    }
+                    let neighbor_x = (x + window_x).saturating_sub(window_w / 2).min(w - 1);
+                    let neighbor_y = (y + window_y).saturating_sub(window_h / 2).min(h - 1);
+                    value = fold(value, pixels[neighbor_y * w + neighbor_x] < center);
+                }
+            }
+            transformed.push(value);
+        }
+    }
+    transformed
+}
+
+fn census_transform(pixels: &[u8], w: usize, h: usize, window: (usize, usize)) -> Vec<u64> {
+    transform(pixels, w, h, window, |bits, darker| (bits << 1) | u64::from(darker))
+}
+
+fn rank_transform(pixels: &[u8], w: usize, h: usize, window: (usize, usize)) -> Vec<u8> {
+    transform(pixels, w, h, window, |rank, darker| rank + u64::from(darker))
+        .into_iter()
+        .map(|rank| rank as u8)
+        .collect()
+}
+
+/// Median of the valid disparities in the `size` square around each valid
+/// one, clipped to the map, as the `median_filter` kernel takes it.
+fn median_filter<T: Disparity>(data: &[T], (w, h): (usize, usize), size: usize) -> Vec<T> {
+    let mut window = Vec::with_capacity(size * size);
+    (0..w * h)
+        .map(|index| {
+            if data[index] == T::INVALID {
+                return T::INVALID;
+            }
+            let (x, y) = (index % w, index / w);
+            let (x_begin, x_end) = (x.saturating_sub(size / 2), (x + size / 2 + 1).min(w));
+            window.clear();
+            for window_y in y.saturating_sub(size / 2)..(y + size / 2 + 1).min(h) {
+                let row = &data[window_y * w + x_begin..window_y * w + x_end];
+                window.extend(row.iter().cloned().filter(|&disparity| disparity != T::INVALID));
+            }
+            window.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            window[window.len() / 2]
+        })
+        .collect()
+}
+
+fn is_connected<T: Disparity>(a: T, b: T, max_difference: f32) -> bool {
+    a != T::INVALID && b != T::INVALID && (a.to_f32() - b.to_f32()).abs() <= max_difference
+}
+
+/// Marks invalid every region of at most `max_size` disparities whose
+/// neighbors differ by at most `max_difference`. The regions are flood
+/// filled here rather than labeled as in the kernels.
+fn remove_speckles<T: Disparity>(
+    data: &mut [T],
+    (w, h): (usize, usize),
+    (max_size, max_difference): (usize, f32),
+) {
+    let mut visited = vec![false; data.len()];
+    let mut region = vec![];
+    for start in 0..data.len() {
+        if visited[start] || data[start] == T::INVALID {
+            continue;
+        }
+        visited[start] = true;
+        region.clear();
+        region.push(start);
+        let mut next = 0;
+        while next < region.len() {
+            let index = region[next];
+            next += 1;
+            let (x, y) = (index % w, index / w);
+            let neighbors = [
+                (x > 0, index.wrapping_sub(1)),
+                (x + 1 < w, index + 1),
+                (y > 0, index.wrapping_sub(w)),
+                (y + 1 < h, index + w),
+            ];
+            for &(inside, neighbor) in neighbors.iter() {
+                if inside && !visited[neighbor] && is_connected(data[index], data[neighbor], max_difference) {
+                    visited[neighbor] = true;
+                    region.push(neighbor);
+                }
+            }
+        }
+        if region.len() <= max_size {
+            for &index in &region {
+                data[index] = T::INVALID;
+            }
+        }
+    }
+}
+
+/// Runs the matcher's median and speckle filters over `disparity`.
+pub(crate) fn post_process<T: Disparity>(matcher: &StereoMatcher, disparity: &mut DisparityMap) {
+    let size = (disparity.width, disparity.height);
+    let data = T::data_mut(&mut disparity.data);
+    if matcher.median_size > 1 {
+        *data = median_filter(data, size, matcher.median_size);
+    }
+    if let Some(speckle_filter) = matcher.speckle_filter {
+        remove_speckles(data, size, speckle_filter);
+    }
+}
+
+pub(crate) fn compute<T: Disparity>(matcher: &StereoMatcher, pair: &StereoPair, max_disparity: usize) -> DisparityMap {
+    let window = matcher.transform_window;
+    let census = if matcher.cost_function == CostFunction::Census {
+        Some((
+            census_transform(&pair.left, pair.width, pair.height, window),
+            census_transform(&pair.right, pair.width, pair.height, window),
+        ))
+    } else {
+        None
+    };
+    let ranks = if matcher.cost_function == CostFunction::Rank {
+        Some((
+            rank_transform(&pair.left, pair.width, pair.height, window),
+            rank_transform(&pair.right, pair.width, pair.height, window),
+        ))
+    } else {
+        None
+    };
+    let (left, right) = match ranks {
+        Some((ref left, ref right)) => (&left[..], &right[..]),
+        None => (&pair.left[..], &pair.right[..]),
+    };
+    let (left_colors, right_colors, channels) = match pair.color {
+        Some((ref left, ref right)) => (&left[..], &right[..], 3),
+        None => (&pair.left[..], &pair.right[..], 1),
+    };
+    let mut search = Search {
+        matcher,
+        pair,
+        diff_len: max_disparity - matcher.min_disparity,
+        left,
+        right,
+        census,
+        left_colors,
+        right_colors,
+        channels,
+        window_sums: None,
+    };
+    if matcher.cost_function == CostFunction::Zncc {
+        search.window_sums = Some(search.get_window_sums());
+    }
+    let block_w = matcher.block_w;
+    let block_h = matcher.block_h;
+    let (result_w, result_h) = match matcher.density {
+        Density::Dense => (pair.width, pair.height),
+        Density::Tiled => (pair.width / block_w, pair.height / block_h),
+    };
+
+    let aggregated = match matcher.aggregation {
+        Aggregation::Sgm => Some(search.aggregate_sgm()),
+        _ => None,
+    };
+    let cross = match matcher.aggregation {
+        Aggregation::Cross => {
+            let arms = search.cross_arms();
+            let row_costs = search.get_cross_row_costs(&arms);
+            Some((arms, row_costs))
+        }
+        _ => None,
+    };
+    let guided_row_sums = match matcher.aggregation {
+        Aggregation::Guided => Some(search.get_guided_row_sums()),
+        _ => None,
+    };
+    let integrals = match matcher.aggregation {
+        Aggregation::IntegralBox => Some(search.get_integrals()),
+        _ => None,
+    };
+
+    let fill_row = |(result_y, (row, confidence_row)): (usize, (&mut [T], &mut [f32]))| {
+        for (result_x, (disparity, confidence)) in row.iter_mut().zip(confidence_row.iter_mut()).enumerate() {
+            let (found, found_confidence) = match (matcher.aggregation, matcher.density) {
+                (Aggregation::Sgm, _) => {
+                    search.find_sgm_disparity(aggregated.as_ref().unwrap(), result_x, result_y)
+                }
+                (Aggregation::AdaptiveWeights, _) => search.find_adaptive_disparity(result_x, result_y),
+                (Aggregation::Cross, _) => {
+                    let (ref arms, ref row_costs) = *cross.as_ref().unwrap();
+                    search.find_cross_disparity(arms, row_costs, result_x, result_y)
+                }
+                (Aggregation::Guided, _) => {
+                    search.find_guided_disparity(guided_row_sums.as_ref().unwrap(), result_x, result_y)
+                }
+                (Aggregation::IntegralBox, Density::Dense) => search.find_integral_disparity(
+                    integrals.as_ref().unwrap(),
+                    search.centered_block(result_x, result_y),
+                ),
+                (Aggregation::IntegralBox, Density::Tiled) => search.find_integral_disparity(
+                    integrals.as_ref().unwrap(),
+                    (result_x * block_w, (result_x + 1) * block_w, result_y * block_h, (result_y + 1) * block_h),
+                ),
+                (Aggregation::Box, Density::Dense) => search.find_centered_disparity(result_x, result_y),
+                (Aggregation::Box, Density::Tiled) => search.find_disparity(
+                    result_x * block_w, (result_x + 1) * block_w,
+                    result_y * block_h, (result_y + 1) * block_h,
+                ),
+            };
+            if matcher.texture_threshold > 0 && search.is_textureless(result_x, result_y) {
+                *disparity = T::INVALID;
+                *confidence = 0.0;
+            } else {
+                *disparity = found;
+                *confidence = found_confidence;
+            }
+        }
+    };
+    let mut data = vec![T::default(); result_w * result_h];
+    let mut confidences = vec![0.0; result_w * result_h];
+    #[cfg(feature = "rayon")]
+    data.par_chunks_mut(result_w).zip(confidences.par_chunks_mut(result_w)).enumerate().for_each(fill_row);
+    #[cfg(not(feature = "rayon"))]
+    data.chunks_mut(result_w).zip(confidences.chunks_mut(result_w)).enumerate().for_each(fill_row);
+
+    DisparityMap {
+        width: result_w,
+        height: result_h,
+        min_disparity: matcher.min_disparity,
+        max_disparity,
+        data: T::into_data(data),
+        confidence: matcher.confidence.filter(|measure| measure.is_from_costs()).map(|measure| ConfidenceMap {
+            width: result_w,
+            height: result_h,
+            measure,
+            data: confidences,
+        }),
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How many disparities `StereoMatcher::compute` returns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Density {
+    /// One disparity per pixel from a block centered on it.
+    #[default]
+    Dense,
+    /// One disparity per non-overlapping block, `width / block_w` by
+    /// `height / block_h`. Much faster, for previews.
+    Tiled,
+}
+
+impl Density {
+    pub fn all() -> [Density; 2] {
+        [Density::Dense, Density::Tiled]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Density::Dense => "dense",
+            Density::Tiled => "tiled",
+        }
+    }
+}
+
+impl fmt::Display for Density {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Density {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Density, String> {
+        Density::all()
+            .iter()
+            .find(|density| density.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown density: {}", s))
+    }
+}
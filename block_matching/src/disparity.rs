@@ -0,0 +1,382 @@
+use image::RgbImage;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+use confidence::ConfidenceMap;
+use error::{MatchError, Result};
+use render::ColorMap;
+
+/// How `DisparityMap::save` writes the map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored through a `ColorMap`; the file type follows the extension.
+    #[default]
+    Image,
+    /// Binary PGM holding the disparity values themselves, 8 or 16 bit.
+    Pgm,
+    /// Grayscale PFM holding the disparity values as floats.
+    Pfm,
+}
+
+impl OutputFormat {
+    pub fn all() -> [OutputFormat; 3] {
+        [OutputFormat::Image, OutputFormat::Pgm, OutputFormat::Pfm]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            OutputFormat::Image => "image",
+            OutputFormat::Pgm => "pgm",
+            OutputFormat::Pfm => "pfm",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<OutputFormat, String> {
+        OutputFormat::all()
+            .iter()
+            .find(|format| format.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown output format: {}", s))
+    }
+}
+
+/// Element type of the disparity buffer the kernels write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisparityType {
+    U8,
+    U16,
+    F32,
+}
+
+impl DisparityType {
+    pub fn all() -> [DisparityType; 3] {
+        [DisparityType::U8, DisparityType::U16, DisparityType::F32]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DisparityType::U8 => "u8",
+            DisparityType::U16 => "u16",
+            DisparityType::F32 => "f32",
+        }
+    }
+
+    /// Largest exclusive `max_disparity` whose disparities stay apart from
+    /// the invalid marker.
+    pub fn max_disparity(&self) -> usize {
+        match *self {
+            DisparityType::U8 => u8::INVALID as usize,
+            DisparityType::U16 => u16::INVALID as usize,
+            DisparityType::F32 => 1 << f32::MANTISSA_DIGITS,
+        }
+    }
+
+    /// Smallest integer type that holds disparities below `max_disparity`.
+    pub fn fitting(max_disparity: usize) -> DisparityType {
+        if max_disparity <= DisparityType::U8.max_disparity() {
+            DisparityType::U8
+        } else {
+            DisparityType::U16
+        }
+    }
+}
+
+impl fmt::Display for DisparityType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DisparityType {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<DisparityType, String> {
+        DisparityType::all()
+            .iter()
+            .find(|disparity_type| disparity_type.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown disparity type: {}", s))
+    }
+}
+
+/// Host side of a `DisparityType`.
+pub(crate) trait Disparity: Copy + Default + PartialOrd + Send + Sync + 'static {
+    const INVALID: Self;
+
+    fn from_disparity(disparity: usize) -> Self;
+
+    /// Only f32 holds fractions; the integer types round.
+    fn from_subpixel(disparity: f32) -> Self;
+
+    fn to_f32(self) -> f32;
+
+    fn into_data(data: Vec<Self>) -> DisparityData;
+
+    /// The disparities of `data`, which must be of this type.
+    fn data_mut(data: &mut DisparityData) -> &mut Vec<Self>;
+}
+
+impl Disparity for u8 {
+    const INVALID: u8 = u8::MAX;
+
+    fn from_disparity(disparity: usize) -> u8 {
+        disparity as u8
+    }
+
+    fn from_subpixel(disparity: f32) -> u8 {
+        disparity.round() as u8
+    }
+
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    fn into_data(data: Vec<u8>) -> DisparityData {
+        DisparityData::U8(data)
+    }
+
+    fn data_mut(data: &mut DisparityData) -> &mut Vec<u8> {
+        match *data {
+            DisparityData::U8(ref mut data) => data,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Disparity for u16 {
+    const INVALID: u16 = u16::MAX;
+
+    fn from_disparity(disparity: usize) -> u16 {
+        disparity as u16
+    }
+
+    fn from_subpixel(disparity: f32) -> u16 {
+        disparity.round() as u16
+    }
+
+    fn to_f32(self) -> f32 {
+        f32::from(self)
+    }
+
+    fn into_data(data: Vec<u16>) -> DisparityData {
+        DisparityData::U16(data)
+    }
+
+    fn data_mut(data: &mut DisparityData) -> &mut Vec<u16> {
+        match *data {
+            DisparityData::U16(ref mut data) => data,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Disparity for f32 {
+    const INVALID: f32 = -1.0;
+
+    fn from_disparity(disparity: usize) -> f32 {
+        disparity as f32
+    }
+
+    fn from_subpixel(disparity: f32) -> f32 {
+        disparity
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn into_data(data: Vec<f32>) -> DisparityData {
+        DisparityData::F32(data)
+    }
+
+    fn data_mut(data: &mut DisparityData) -> &mut Vec<f32> {
+        match *data {
+            DisparityData::F32(ref mut data) => data,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Disparities as written by the kernels. Invalid entries hold `u8::MAX`,
+/// `u16::MAX` or `-1.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DisparityData {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+impl DisparityData {
+    pub fn disparity_type(&self) -> DisparityType {
+        match *self {
+            DisparityData::U8(_) => DisparityType::U8,
+            DisparityData::U16(_) => DisparityType::U16,
+            DisparityData::F32(_) => DisparityType::F32,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match *self {
+            DisparityData::U8(ref data) => data.len(),
+            DisparityData::U16(ref data) => data.len(),
+            DisparityData::F32(ref data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Disparity at `index`, or `None` where it is invalid.
+    pub fn get(&self, index: usize) -> Option<f32> {
+        match *self {
+            DisparityData::U8(ref data) => Some(data[index]).filter(|&d| d != u8::INVALID).map(f32::from),
+            DisparityData::U16(ref data) => Some(data[index]).filter(|&d| d != u16::INVALID).map(f32::from),
+            DisparityData::F32(ref data) => Some(data[index]).filter(|&d| d != f32::INVALID),
+        }
+    }
+
+    pub(crate) fn invalidate(&mut self, index: usize) {
+        match *self {
+            DisparityData::U8(ref mut data) => data[index] = u8::INVALID,
+            DisparityData::U16(ref mut data) => data[index] = u16::INVALID,
+            DisparityData::F32(ref mut data) => data[index] = f32::INVALID,
+        }
+    }
+}
+
+/// Disparity of each pixel, or of each block for `Density::Tiled`, stored
+/// row by row.
+pub struct DisparityMap {
+    pub width: usize,
+    pub height: usize,
+    pub min_disparity: usize,
+    /// Exclusive upper bound of the searched disparities.
+    pub max_disparity: usize,
+    pub data: DisparityData,
+    /// Confidence of each disparity, when the matcher measures one.
+    pub confidence: Option<ConfidenceMap>,
+}
+
+impl DisparityMap {
+    /// Disparity at (`x`, `y`), or `None` where it is invalid.
+    pub fn get(&self, x: usize, y: usize) -> Option<f32> {
+        self.data.get(y * self.width + x)
+    }
+
+    /// Marks the disparity at (`x`, `y`) invalid, with no confidence.
+    pub(crate) fn invalidate(&mut self, x: usize, y: usize) {
+        let index = y * self.width + x;
+        self.data.invalidate(index);
+        if let Some(ref mut confidence) = self.confidence {
+            confidence.data[index] = 0.0;
+        }
+    }
+
+    /// Clears the confidence of every invalid disparity.
+    pub(crate) fn clear_invalid_confidence(&mut self) {
+        if let Some(ref mut confidence) = self.confidence {
+            for (i, value) in confidence.data.iter_mut().enumerate() {
+                if self.data.get(i).is_none() {
+                    *value = 0.0;
+                }
+            }
+        }
+    }
+
+    fn check_len(&self) -> Result<()> {
+        if self.data.len() != self.width * self.height {
+            return Err(MatchError::InvalidParameter(format!(
+                "{} disparities do not fill a {}x{} map",
+                self.data.len(), self.width, self.height
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_range(&self) -> Result<()> {
+        if self.min_disparity >= self.max_disparity {
+            return Err(MatchError::InvalidParameter(format!(
+                "disparity range {}..{} is empty", self.min_disparity, self.max_disparity
+            )));
+        }
+        Ok(())
+    }
+
+    /// Invalid disparities take the colormap's `invalid_color`.
+    pub fn to_rgb_image(&self, colormap: ColorMap) -> Result<RgbImage> {
+        self.check_len()?;
+        self.check_range()?;
+        let mut pixels = vec![];
+        let diff_len_f32 = (self.max_disparity - self.min_disparity) as f32;
+        for i in 0..self.data.len() {
+            match self.data.get(i) {
+                Some(p) => {
+                    let ratio = (p - self.min_disparity as f32) / diff_len_f32;
+                    pixels.extend(colormap.color(ratio));
+                }
+                None => pixels.extend(&colormap.invalid_color()),
+            }
+        }
+        Ok(RgbImage::from_raw(self.width as u32, self.height as u32, pixels).unwrap())
+    }
+
+    pub fn save(&self, file_name: &str, format: OutputFormat, colormap: ColorMap) -> Result<()> {
+        self.check_len()?;
+        let saved = match format {
+            OutputFormat::Image => self.to_rgb_image(colormap)?.save(file_name),
+            OutputFormat::Pgm => self.save_pgm(file_name),
+            OutputFormat::Pfm => self.save_pfm(file_name),
+        };
+        saved.map_err(|error| MatchError::Output { file_name: file_name.to_string(), error })
+    }
+
+    fn save_pgm(&self, file_name: &str) -> io::Result<()> {
+        if let DisparityData::F32(_) = self.data {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "PGM holds only integer disparities; save f32 disparities as PFM",
+            ));
+        }
+        let mut file = BufWriter::new(File::create(file_name)?);
+        match self.data {
+            DisparityData::U8(ref data) => {
+                write!(file, "P5\n{} {}\n{}\n", self.width, self.height, u8::MAX)?;
+                file.write_all(data)?;
+            }
+            DisparityData::U16(ref data) => {
+                write!(file, "P5\n{} {}\n{}\n", self.width, self.height, u16::MAX)?;
+                for d in data {
+                    file.write_all(&d.to_be_bytes())?;
+                }
+            }
+            DisparityData::F32(_) => unreachable!(),
+        }
+        file.flush()
+    }
+
+    /// Rows go bottom to top as PFM requires; invalid disparities are -1.
+    fn save_pfm(&self, file_name: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(file_name)?);
+        write!(file, "Pf\n{} {}\n-1.0\n", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let d = self.get(x, y).unwrap_or(f32::INVALID);
+                file.write_all(&d.to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How `get_diffs` is spread over work items. Every strategy produces the
+/// same disparity map; they only differ in speed per device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DispatchStrategy {
+    /// 3D global work size with the disparity taken from `get_global_id(2)`.
+    ThreeDims,
+    /// 2D global work size with a loop over disparities inside the kernel.
+    #[default]
+    TwoDimsWithLoopInKernel,
+    /// 2D global work size, enqueued once per disparity after replacing the
+    /// `diff_index` argument.
+    TwoDimsWithReplacingArg,
+}
+
+impl DispatchStrategy {
+    pub fn all() -> [DispatchStrategy; 3] {
+        [
+            DispatchStrategy::ThreeDims,
+            DispatchStrategy::TwoDimsWithLoopInKernel,
+            DispatchStrategy::TwoDimsWithReplacingArg,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DispatchStrategy::ThreeDims => "3dims",
+            DispatchStrategy::TwoDimsWithLoopInKernel => "2dims-loop",
+            DispatchStrategy::TwoDimsWithReplacingArg => "2dims-replacing-arg",
+        }
+    }
+}
+
+impl fmt::Display for DispatchStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DispatchStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DispatchStrategy, String> {
+        DispatchStrategy::all()
+            .iter()
+            .find(|strategy| strategy.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown dispatch strategy: {}", s))
+    }
+}
@@ -0,0 +1,67 @@
+use image::ImageError;
+#[cfg(feature = "opencl")]
+use ocl;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = ::std::result::Result<T, MatchError>;
+
+#[derive(Debug)]
+pub enum MatchError {
+    /// An input image could not be opened or decoded.
+    Image { file_name: String, error: ImageError },
+    /// The left and right images differ in size.
+    SizeMismatch { left: (usize, usize), right: (usize, usize) },
+    /// Matcher settings or pixel data that cannot be used together.
+    InvalidParameter(String),
+    /// No OpenCL platform or device is available.
+    Platform(String),
+    /// The kernels failed to build; the message holds the build log.
+    KernelBuild(String),
+    /// Creating a buffer, enqueuing a kernel or reading a result failed.
+    #[cfg(feature = "opencl")]
+    Enqueue(ocl::Error),
+    /// The result could not be written.
+    Output { file_name: String, error: io::Error },
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MatchError::Image { ref file_name, ref error } => {
+                write!(f, "failed to load {}: {}", file_name, error)
+            }
+            MatchError::SizeMismatch { left, right } => write!(
+                f,
+                "left image is {}x{} but right image is {}x{}",
+                left.0, left.1, right.0, right.1
+            ),
+            MatchError::InvalidParameter(ref message) => write!(f, "invalid parameter: {}", message),
+            MatchError::Platform(ref message) => write!(f, "OpenCL platform unavailable: {}", message),
+            MatchError::KernelBuild(ref log) => write!(f, "failed to build kernels:\n{}", log),
+            #[cfg(feature = "opencl")]
+            MatchError::Enqueue(ref error) => write!(f, "OpenCL command failed: {}", error),
+            MatchError::Output { ref file_name, ref error } => {
+                write!(f, "failed to write {}: {}", file_name, error)
+            }
+        }
+    }
+}
+
+impl Error for MatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            MatchError::Image { ref error, .. } => Some(error),
+            MatchError::Output { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "opencl")]
+impl From<ocl::Error> for MatchError {
+    fn from(error: ocl::Error) -> MatchError {
+        MatchError::Enqueue(error)
+    }
+}
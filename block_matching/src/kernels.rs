@@ -0,0 +1,1549 @@
+const SRC: &str = r#"
+    // Multiply-adds stay two roundings, as on the CPU backend.
+    #pragma OPENCL FP_CONTRACT OFF
+
+    #define BORDER_CLAMP 0
+    #define BORDER_MIRROR 1
+    #define BORDER_CONSTANT 2
+    #define BORDER_INVALID 3
+
+    #define COST_SAD 0
+    #define COST_SSD 1
+    #define COST_ZNCC 2
+    #define COST_CENSUS 3
+    #define COST_RANK 4
+    #define COST_BT 5
+
+    #if COST_FUNCTION == COST_ZNCC
+    typedef float window_cost_t;
+    #else
+    typedef unsigned int window_cost_t;
+    #endif
+
+    #define SUBPIXEL_NONE 0
+    #define SUBPIXEL_PARABOLA 1
+    #define SUBPIXEL_EQUIANGULAR 2
+
+    #define CONFIDENCE_NONE 0
+    #define CONFIDENCE_RATIO 1
+    #define CONFIDENCE_CURVATURE 2
+    #define CONFIDENCE_DIFFERENCE 3
+
+    // Costs around the smallest of those seen so far: one disparity below
+    // and above it, the smallest of the disparities not next to it, and the
+    // largest of all.
+    typedef struct {
+        float lower;
+        float upper;
+        float second;
+        float highest;
+        // Last cost seen, and the smallest of those before it.
+        float previous;
+        float early;
+    } neighbor_costs;
+
+    // Feeds the cost of index i, after min_index was moved to the smallest
+    // cost so far.
+    void track_neighbor_costs(neighbor_costs* neighbors, size_t i, size_t min_index, float cost) {
+        if (i == min_index) {
+            neighbors->lower = neighbors->previous;
+            neighbors->second = neighbors->early;
+        } else if (i == min_index + 1) {
+            neighbors->upper = cost;
+        } else if (neighbors->second > cost) {
+            neighbors->second = cost;
+        }
+        if (i > 0 && neighbors->early > neighbors->previous)
+            neighbors->early = neighbors->previous;
+        neighbors->previous = cost;
+        neighbors->highest = fmax(neighbors->highest, cost);
+    }
+
+    // Disparity of the smallest cost center, found at index i of the
+    // valid_len searched ones, moved toward the vertex of the fit through it
+    // and the costs one disparity below and above when both were searched.
+    // Invalid when another disparity not next to it ties with it or costs
+    // less than UNIQUENESS_RATIO percent more. Valid disparities write their
+    // CONFIDENCE_MEASURE, between 0 and 1, to confidence.
+    DISPARITY_T select_disparity(
+                 size_t min_disparity,
+                 size_t i,
+                 size_t valid_len,
+                 float center,
+                 neighbor_costs* neighbors,
+                 __global float* confidence) {
+    #if UNIQUENESS_RATIO > 0
+        if (neighbors->second <= center || (neighbors->second - center) * 100.0f < center * UNIQUENESS_RATIO)
+            return INVALID_DISPARITY;
+    #endif
+    #if CONFIDENCE_MEASURE == CONFIDENCE_RATIO
+        // How much cheaper the match is than the best one not next to it.
+        *confidence = neighbors->second > 0.0f ? 1.0f - center / neighbors->second : 0.0f;
+    #elif CONFIDENCE_MEASURE == CONFIDENCE_CURVATURE
+        // Curvature relative to a V from the higher neighbor down to zero.
+        float steepest = 2.0f * fmax(neighbors->lower, neighbors->upper);
+        *confidence = i > 0 && i + 1 < valid_len && steepest > 0.0f
+            ? (neighbors->lower - 2.0f * center + neighbors->upper) / steepest
+            : 0.0f;
+    #elif CONFIDENCE_MEASURE == CONFIDENCE_DIFFERENCE
+        // How much cheaper the match is than the best one not next to it,
+        // relative to the spread of all the costs.
+        float spread = neighbors->highest - center;
+        *confidence = spread > 0.0f ? fmin((neighbors->second - center) / spread, 1.0f) : 0.0f;
+    #endif
+    #if SUBPIXEL_FIT == SUBPIXEL_NONE
+        return (DISPARITY_T) (min_disparity + i);
+    #else
+        float lower = neighbors->lower;
+        float upper = neighbors->upper;
+        float offset = 0.0f;
+        if (i > 0 && i + 1 < valid_len) {
+    #if SUBPIXEL_FIT == SUBPIXEL_PARABOLA
+            float curvature = lower - 2.0f * center + upper;
+            if (curvature > 0.0f)
+                offset = (lower - upper) / (2.0f * curvature);
+    #else
+            float slope = fmax(lower - center, upper - center);
+            if (slope > 0.0f)
+                offset = (lower - upper) / (2.0f * slope);
+    #endif
+        }
+        return (DISPARITY_T) (min_disparity + i) + offset;
+    #endif
+    }
+
+    PIXEL_T get_left_pixel(
+                 __global PIXEL_T* left_pixels,
+                 size_t w,
+                 size_t x,
+                 size_t y) {
+        if (x >= w) {
+    #if BORDER_POLICY == BORDER_MIRROR
+            x = x < 2 * (w - 1) ? 2 * (w - 1) - x : 0;
+    #elif BORDER_POLICY == BORDER_CONSTANT
+    #if COST_FUNCTION == COST_CENSUS || COST_FUNCTION == COST_RANK
+            // No pixel of a constant region is darker than its center.
+            return 0;
+    #else
+            return BORDER_VALUE;
+    #endif
+    #else
+            x = w - 1;
+    #endif
+        }
+        return left_pixels[y * w + x];
+    }
+
+    unsigned short get_diff(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 size_t w,
+                 size_t x,
+                 size_t y,
+                 size_t disparity) {
+        PIXEL_T left = get_left_pixel(left_pixels, w, x + disparity, y);
+        PIXEL_T right = right_pixels[y * w + x];
+    #if COST_FUNCTION == COST_CENSUS
+        // Hamming distance of the census bit strings.
+        return (unsigned short) popcount(left ^ right);
+    #elif COST_FUNCTION == COST_ZNCC
+        // Only the cross term of the correlation depends on both images.
+        return (unsigned short) left * right;
+    #elif COST_FUNCTION == COST_BT
+        // Birchfield-Tomasi dissimilarity in half gray levels: each pixel is
+        // compared with the range the other image spans half a pixel around
+        // the matched one, interpolated linearly.
+        size_t left_x = x + disparity;
+        int left2 = 2 * left;
+        int left_minus = left + get_left_pixel(left_pixels, w, left_x > 0 ? left_x - 1 : left_x, y);
+        int left_plus = left + get_left_pixel(left_pixels, w, left_x + 1, y);
+        int right2 = 2 * right;
+        int right_minus = right + right_pixels[y * w + (x > 0 ? x - 1 : x)];
+        int right_plus = right + right_pixels[y * w + (x + 1 < w ? x + 1 : x)];
+        int left_min = min(left2, min(left_minus, left_plus));
+        int left_max = max(left2, max(left_minus, left_plus));
+        int right_min = min(right2, min(right_minus, right_plus));
+        int right_max = max(right2, max(right_minus, right_plus));
+        int right_to_left = max(0, max(right2 - left_max, left_min - right2));
+        int left_to_right = max(0, max(left2 - right_max, right_min - left2));
+        return (unsigned short) min(right_to_left, left_to_right);
+    #else
+        unsigned short diff = left > right ? left - right : right - left;
+    #if COST_FUNCTION == COST_SSD
+        return diff * diff;
+    #else
+        return diff;
+    #endif
+    #endif
+    }
+
+    // Bit string of the pixels in the window centered on (x, y) that are darker
+    // than the center, the first row's leftmost pixel in the highest bit.
+    // Coordinates past the image are clamped.
+    __kernel void census_transform(
+                 __global unsigned char* pixels,
+                 __global PIXEL_T* census,
+                 size_t w,
+                 size_t h,
+                 size_t window_w,
+                 size_t window_h) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        unsigned char center = pixels[y * w + x];
+        PIXEL_T bits = 0;
+        size_t window_x, window_y;
+        for (window_y = 0; window_y < window_h; window_y++) {
+            for (window_x = 0; window_x < window_w; window_x++) {
+                if (window_x == window_w / 2 && window_y == window_h / 2)
+                    continue;
+                long neighbor_x = (long) (x + window_x) - (long) (window_w / 2);
+                long neighbor_y = (long) (y + window_y) - (long) (window_h / 2);
+                neighbor_x = clamp(neighbor_x, 0L, (long) w - 1);
+                neighbor_y = clamp(neighbor_y, 0L, (long) h - 1);
+                bits = (bits << 1) | (pixels[neighbor_y * w + neighbor_x] < center);
+            }
+        }
+        census[y * w + x] = bits;
+    }
+
+    // Count of the pixels in the window centered on (x, y) that are darker than
+    // the center. Coordinates past the image are clamped.
+    __kernel void rank_transform(
+                 __global unsigned char* pixels,
+                 __global unsigned char* ranks,
+                 size_t w,
+                 size_t h,
+                 size_t window_w,
+                 size_t window_h) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        unsigned char center = pixels[y * w + x];
+        unsigned char rank = 0;
+        size_t window_x, window_y;
+        for (window_y = 0; window_y < window_h; window_y++) {
+            for (window_x = 0; window_x < window_w; window_x++) {
+                long neighbor_x = (long) (x + window_x) - (long) (window_w / 2);
+                long neighbor_y = (long) (y + window_y) - (long) (window_h / 2);
+                neighbor_x = clamp(neighbor_x, 0L, (long) w - 1);
+                neighbor_y = clamp(neighbor_y, 0L, (long) h - 1);
+                rank += pixels[neighbor_y * w + neighbor_x] < center;
+            }
+        }
+        ranks[y * w + x] = rank;
+    }
+
+    __kernel void get_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        size_t target_index = y * w + x;
+        size_t diff_index;
+        for (diff_index = 0; diff_index < diff_len; ++diff_index) {
+            diffs[target_index * diff_len + diff_index] =
+                get_diff(left_pixels, right_pixels, w, x, y, min_disparity + diff_index);
+        }
+    }
+
+    __kernel void get_diffs_3dims(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        size_t diff_index = get_global_id(2);
+        size_t target_index = y * w + x;
+        diffs[target_index * diff_len + diff_index] =
+            get_diff(left_pixels, right_pixels, w, x, y, min_disparity + diff_index);
+    }
+
+    __kernel void get_diffs_at_index(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 size_t diff_index) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        size_t target_index = y * w + x;
+        diffs[target_index * diff_len + diff_index] =
+            get_diff(left_pixels, right_pixels, w, x, y, min_disparity + diff_index);
+    }
+
+    // Cost of disparity min_disparity + i at (x, y), read from the cost
+    // volume or, when there is none, computed on the fly.
+    unsigned short get_cost(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 size_t w,
+                 size_t x,
+                 size_t y,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 size_t i) {
+        if (diffs)
+            return diffs[(y * w + x) * diff_len + i];
+        return get_diff(left_pixels, right_pixels, w, x, y, min_disparity + i);
+    }
+
+    #define WINDOW_LEFT_SUM 0
+    #define WINDOW_LEFT_SQUARE_SUM 1
+    #define WINDOW_RIGHT_SUM 2
+    #define WINDOW_RIGHT_SQUARE_SUM 3
+
+    // Row sums of the left image as matching reads it, up to every searched
+    // disparity past its right edge, of the right image, and of their
+    // squares, interleaved per pixel in rows w + min_disparity + diff_len
+    // wide.
+    __kernel void get_row_window_sums(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned int* window_sums,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t y = get_global_id(0);
+        if (y >= h)
+            return;
+        size_t sums_w = w + min_disparity + diff_len;
+        size_t x;
+        unsigned int left_sum = 0, left_square_sum = 0, right_sum = 0, right_square_sum = 0;
+        for (x = 0; x < sums_w; x++) {
+            unsigned int left = get_left_pixel(left_pixels, w, x, y);
+            unsigned int right = x < w ? right_pixels[y * w + x] : 0;
+            left_sum += left;
+            left_square_sum += left * left;
+            right_sum += right;
+            right_square_sum += right * right;
+            window_sums[(y * sums_w + x) * 4 + WINDOW_LEFT_SUM] = left_sum;
+            window_sums[(y * sums_w + x) * 4 + WINDOW_LEFT_SQUARE_SUM] = left_square_sum;
+            window_sums[(y * sums_w + x) * 4 + WINDOW_RIGHT_SUM] = right_sum;
+            window_sums[(y * sums_w + x) * 4 + WINDOW_RIGHT_SQUARE_SUM] = right_square_sum;
+        }
+    }
+
+    // Adds the row sums down each column in place, as
+    // accumulate_column_integrals does for the cost volume.
+    __kernel void accumulate_column_window_sums(
+                 __global unsigned int* window_sums,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t plane = get_global_id(1);
+        size_t sums_w = w + min_disparity + diff_len;
+        if (x >= sums_w || plane >= 4)
+            return;
+        size_t y;
+        for (y = 1; y < h; y++)
+            window_sums[(y * sums_w + x) * 4 + plane] += window_sums[((y - 1) * sums_w + x) * 4 + plane];
+    }
+
+    // Sum of a plane of window_sums over [x_begin, x_end) x [y_begin, y_end).
+    unsigned int get_window_sum(
+                 __global unsigned int* window_sums,
+                 size_t sums_w,
+                 size_t x_begin,
+                 size_t x_end,
+                 size_t y_begin,
+                 size_t y_end,
+                 size_t plane) {
+        unsigned int sum = window_sums[((y_end - 1) * sums_w + x_end - 1) * 4 + plane];
+        if (x_begin > 0)
+            sum -= window_sums[((y_end - 1) * sums_w + x_begin - 1) * 4 + plane];
+        if (y_begin > 0)
+            sum -= window_sums[((y_begin - 1) * sums_w + x_end - 1) * 4 + plane];
+        if (x_begin > 0 && y_begin > 0)
+            sum += window_sums[((y_begin - 1) * sums_w + x_begin - 1) * 4 + plane];
+        return sum;
+    }
+
+    // Cost of disparity min_disparity + i over the window [x_begin, x_end) x
+    // [y_begin, y_end). ZNCC is negated so that the best match is always the
+    // smallest cost.
+    window_cost_t get_window_cost(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global unsigned int* window_sums,
+                 size_t w,
+                 size_t x_begin,
+                 size_t x_end,
+                 size_t y_begin,
+                 size_t y_end,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 size_t i) {
+        size_t x, y;
+        unsigned int diff_point = 0;
+        for (x = x_begin; x < x_end; x++) {
+            for (y = y_begin; y < y_end; y++) {
+                diff_point += (unsigned int) get_cost(
+                    left_pixels, right_pixels, diffs, w, x, y, min_disparity, diff_len, i);
+            }
+        }
+    #if COST_FUNCTION == COST_ZNCC
+        size_t sums_w = w + min_disparity + diff_len;
+        size_t left_begin = x_begin + min_disparity + i;
+        size_t left_end = x_end + min_disparity + i;
+        unsigned int left_sum = get_window_sum(
+            window_sums, sums_w, left_begin, left_end, y_begin, y_end, WINDOW_LEFT_SUM);
+        unsigned int left_square_sum = get_window_sum(
+            window_sums, sums_w, left_begin, left_end, y_begin, y_end, WINDOW_LEFT_SQUARE_SUM);
+        unsigned int right_sum = get_window_sum(
+            window_sums, sums_w, x_begin, x_end, y_begin, y_end, WINDOW_RIGHT_SUM);
+        unsigned int right_square_sum = get_window_sum(
+            window_sums, sums_w, x_begin, x_end, y_begin, y_end, WINDOW_RIGHT_SQUARE_SUM);
+        // Moments scaled by the pixel count n stay exact integers.
+        long n = (x_end - x_begin) * (y_end - y_begin);
+        long covariance = n * diff_point - (long) left_sum * right_sum;
+        long left_variance = n * left_square_sum - (long) left_sum * left_sum;
+        long right_variance = n * right_square_sum - (long) right_sum * right_sum;
+        // A flat left window correlates with nothing, which is the worst
+        // cost; find_disparity has already given up on a flat right one.
+        if (left_variance == 0 || right_variance == 0)
+            return 1.0f;
+        return -(float) covariance / sqrt((float) left_variance * (float) right_variance);
+    #else
+        return diff_point;
+    #endif
+    }
+
+    // Disparity whose window cost is the smallest.
+    DISPARITY_T find_disparity(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global unsigned int* window_sums,
+                 size_t w,
+                 size_t x_begin,
+                 size_t x_end,
+                 size_t y_begin,
+                 size_t y_end,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 __global float* confidence) {
+        size_t valid_len = diff_len;
+    #if BORDER_POLICY == BORDER_INVALID
+        // Only disparities that keep the shifted window inside the left image.
+        if (x_end + min_disparity > w)
+            return INVALID_DISPARITY;
+        if (w - x_end - min_disparity + 1 < valid_len)
+            valid_len = w - x_end - min_disparity + 1;
+    #endif
+    #if COST_FUNCTION == COST_ZNCC
+        // A flat right window correlates with nothing at any disparity.
+        size_t sums_w = w + min_disparity + diff_len;
+        long n = (x_end - x_begin) * (y_end - y_begin);
+        unsigned int right_sum = get_window_sum(
+            window_sums, sums_w, x_begin, x_end, y_begin, y_end, WINDOW_RIGHT_SUM);
+        unsigned int right_square_sum = get_window_sum(
+            window_sums, sums_w, x_begin, x_end, y_begin, y_end, WINDOW_RIGHT_SQUARE_SUM);
+        if (n * right_square_sum == (long) right_sum * right_sum)
+            return INVALID_DISPARITY;
+    #endif
+        size_t i;
+        size_t min_diff_index;
+        window_cost_t min_diff_point;
+        neighbor_costs neighbors = {0.0f, 0.0f, INFINITY, -INFINITY, 0.0f, INFINITY};
+        for (i = 0; i < valid_len; i++) {
+            window_cost_t diff_point = get_window_cost(
+                left_pixels, right_pixels, diffs, window_sums, w,
+                x_begin, x_end, y_begin, y_end, min_disparity, diff_len, i);
+            if (i == 0 || min_diff_point > diff_point) {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            track_neighbor_costs(&neighbors, i, min_diff_index, diff_point);
+        }
+        return select_disparity(
+            min_disparity, min_diff_index, valid_len, min_diff_point, &neighbors, confidence);
+    }
+
+    // Disparity of a block centered on (x, y). The block is clipped where it
+    // leaves the image.
+    DISPARITY_T find_centered_disparity(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global unsigned int* window_sums,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t x,
+                 size_t y,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 __global float* confidence) {
+        size_t x_begin = x < block_w / 2 ? 0 : x - block_w / 2;
+        size_t y_begin = y < block_h / 2 ? 0 : y - block_h / 2;
+        size_t x_end = x + block_w - block_w / 2;
+        size_t y_end = y + block_h - block_h / 2;
+        if (x_end > w)
+            x_end = w;
+        if (y_end > h)
+            y_end = h;
+        return find_disparity(left_pixels, right_pixels, diffs, window_sums, w,
+                              x_begin, x_end, y_begin, y_end, min_disparity, diff_len, confidence);
+    }
+
+    __kernel void get_result_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global unsigned int* window_sums,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t result_w,
+                 size_t result_h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t result_x = get_global_id(0);
+        size_t result_y = get_global_id(1);
+        if (result_x >= result_w || result_y >= result_h)
+            return;
+        result_diffs[result_y * result_w + result_x] = find_disparity(
+            left_pixels, right_pixels, diffs, window_sums, w,
+            result_x * block_w, (result_x + 1) * block_w,
+            result_y * block_h, (result_y + 1) * block_h,
+            min_disparity, diff_len, confidences + result_y * result_w + result_x);
+    }
+
+    __kernel void get_dense_result_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global unsigned int* window_sums,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        result_diffs[y * w + x] = find_centered_disparity(
+            left_pixels, right_pixels, diffs, window_sums, w, h, block_w, block_h, x, y, min_disparity, diff_len,
+            confidences + y * w + x);
+    }
+
+    // get_result_diffs without a cost volume: every block cost is summed from
+    // the pixels directly.
+    __kernel void get_streamed_result_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned int* window_sums,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t result_w,
+                 size_t result_h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t result_x = get_global_id(0);
+        size_t result_y = get_global_id(1);
+        if (result_x >= result_w || result_y >= result_h)
+            return;
+        result_diffs[result_y * result_w + result_x] = find_disparity(
+            left_pixels, right_pixels, 0, window_sums, w,
+            result_x * block_w, (result_x + 1) * block_w,
+            result_y * block_h, (result_y + 1) * block_h,
+            min_disparity, diff_len, confidences + result_y * result_w + result_x);
+    }
+
+    // get_dense_result_diffs without a cost volume.
+    __kernel void get_streamed_dense_result_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned int* window_sums,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        result_diffs[y * w + x] = find_centered_disparity(
+            left_pixels, right_pixels, 0, window_sums, w, h, block_w, block_h, x, y, min_disparity, diff_len,
+            confidences + y * w + x);
+    }
+
+    // Index of the left color read at (x, y). Past the right edge the
+    // colors are clamped, or mirrored for BORDER_MIRROR.
+    size_t get_left_color_index(
+                 size_t w,
+                 size_t x,
+                 size_t y) {
+        if (x >= w) {
+    #if BORDER_POLICY == BORDER_MIRROR
+            x = x < 2 * (w - 1) ? 2 * (w - 1) - x : 0;
+    #else
+            x = w - 1;
+    #endif
+        }
+        return y * w + x;
+    }
+
+    // Yoon-Kweon support weight of pixel b for center a of the same image.
+    float get_support_weight(
+                 __global uchar* colors,
+                 size_t channels,
+                 size_t a,
+                 size_t b,
+                 float spatial_distance,
+                 float gamma_color,
+                 float gamma_proximity) {
+        unsigned int square_sum = 0;
+        size_t c;
+        for (c = 0; c < channels; c++) {
+            int diff = (int) colors[a * channels + c] - (int) colors[b * channels + c];
+            square_sum += diff * diff;
+        }
+        return exp(-(sqrt((float) square_sum) / gamma_color + spatial_distance / gamma_proximity));
+    }
+
+    // Disparity of a block centered on (x, y) whose per-pixel costs,
+    // averaged under the support weights of both images, are the smallest.
+    DISPARITY_T find_adaptive_disparity(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global uchar* left_colors,
+                 __global uchar* right_colors,
+                 size_t channels,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t x,
+                 size_t y,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 float gamma_color,
+                 float gamma_proximity,
+                 __global float* confidence) {
+        size_t x_begin = x < block_w / 2 ? 0 : x - block_w / 2;
+        size_t y_begin = y < block_h / 2 ? 0 : y - block_h / 2;
+        size_t x_end = min(x + block_w - block_w / 2, w);
+        size_t y_end = min(y + block_h - block_h / 2, h);
+        size_t valid_len = diff_len;
+    #if BORDER_POLICY == BORDER_INVALID
+        if (x_end + min_disparity > w)
+            return INVALID_DISPARITY;
+        if (w - x_end - min_disparity + 1 < valid_len)
+            valid_len = w - x_end - min_disparity + 1;
+    #endif
+        size_t i, block_x, block_y;
+        size_t min_diff_index;
+        float min_diff_point;
+        neighbor_costs neighbors = {0.0f, 0.0f, INFINITY, -INFINITY, 0.0f, INFINITY};
+        for (i = 0; i < valid_len; i++) {
+            size_t disparity = min_disparity + i;
+            size_t left_center = get_left_color_index(w, x + disparity, y);
+            float weighted_sum = 0.0f;
+            float weight_sum = 0.0f;
+            for (block_x = x_begin; block_x < x_end; block_x++) {
+                for (block_y = y_begin; block_y < y_end; block_y++) {
+                    long dx = (long) block_x - (long) x;
+                    long dy = (long) block_y - (long) y;
+                    float spatial_distance = sqrt((float) (dx * dx + dy * dy));
+                    float weight = get_support_weight(
+                        right_colors, channels, y * w + x, block_y * w + block_x,
+                        spatial_distance, gamma_color, gamma_proximity);
+                    weight *= get_support_weight(
+                        left_colors, channels, left_center, get_left_color_index(w, block_x + disparity, block_y),
+                        spatial_distance, gamma_color, gamma_proximity);
+                    weighted_sum += weight * (float) get_cost(
+                        left_pixels, right_pixels, diffs, w, block_x, block_y, min_disparity, diff_len, i);
+                    weight_sum += weight;
+                }
+            }
+            float diff_point = weighted_sum / weight_sum;
+            if (i == 0 || min_diff_point > diff_point) {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            track_neighbor_costs(&neighbors, i, min_diff_index, diff_point);
+        }
+        return select_disparity(
+            min_disparity, min_diff_index, valid_len, min_diff_point, &neighbors, confidence);
+    }
+
+    __kernel void get_adaptive_result_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global uchar* left_colors,
+                 __global uchar* right_colors,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t channels,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 float gamma_color,
+                 float gamma_proximity) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        result_diffs[y * w + x] = find_adaptive_disparity(
+            left_pixels, right_pixels, diffs, left_colors, right_colors, channels, w, h,
+            block_w, block_h, x, y, min_disparity, diff_len, gamma_color, gamma_proximity,
+            confidences + y * w + x);
+    }
+
+    // get_adaptive_result_diffs without a cost volume.
+    __kernel void get_streamed_adaptive_result_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global uchar* left_colors,
+                 __global uchar* right_colors,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t channels,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 float gamma_color,
+                 float gamma_proximity) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        result_diffs[y * w + x] = find_adaptive_disparity(
+            left_pixels, right_pixels, 0, left_colors, right_colors, channels, w, h,
+            block_w, block_h, x, y, min_disparity, diff_len, gamma_color, gamma_proximity,
+            confidences + y * w + x);
+    }
+
+    // Running sums of every cost slice along each row, one work item per
+    // disparity and row.
+    __kernel void get_row_integrals(
+                 __global unsigned short* diffs,
+                 __global unsigned int* integrals,
+                 size_t w,
+                 size_t h,
+                 size_t diff_len) {
+        size_t i = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (i >= diff_len || y >= h)
+            return;
+        size_t x;
+        unsigned int sum = 0;
+        for (x = 0; x < w; x++) {
+            sum += diffs[(y * w + x) * diff_len + i];
+            integrals[(y * w + x) * diff_len + i] = sum;
+        }
+    }
+
+    // Adds the row integrals down each column in place, which makes them
+    // integral images: the sum of every cost above and left of a pixel,
+    // itself included. The sums wrap, but window sums taken from them are
+    // exact as long as they fit in a uint.
+    __kernel void accumulate_column_integrals(
+                 __global unsigned int* integrals,
+                 size_t w,
+                 size_t h,
+                 size_t diff_len) {
+        size_t i = get_global_id(0);
+        size_t x = get_global_id(1);
+        if (i >= diff_len || x >= w)
+            return;
+        size_t y;
+        for (y = 1; y < h; y++)
+            integrals[(y * w + x) * diff_len + i] += integrals[((y - 1) * w + x) * diff_len + i];
+    }
+
+    // Sum of the costs of slice i in x < x_end and y < y_end.
+    unsigned int get_integral(
+                 __global unsigned int* integrals,
+                 size_t w,
+                 size_t x_end,
+                 size_t y_end,
+                 size_t diff_len,
+                 size_t i) {
+        if (x_end == 0 || y_end == 0)
+            return 0;
+        return integrals[((y_end - 1) * w + x_end - 1) * diff_len + i];
+    }
+
+    // find_disparity with window costs from the integral images, in
+    // constant time whatever the window size.
+    DISPARITY_T find_integral_disparity(
+                 __global unsigned int* integrals,
+                 size_t w,
+                 size_t x_begin,
+                 size_t x_end,
+                 size_t y_begin,
+                 size_t y_end,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 __global float* confidence) {
+        size_t valid_len = diff_len;
+    #if BORDER_POLICY == BORDER_INVALID
+        if (x_end + min_disparity > w)
+            return INVALID_DISPARITY;
+        if (w - x_end - min_disparity + 1 < valid_len)
+            valid_len = w - x_end - min_disparity + 1;
+    #endif
+        size_t i;
+        size_t min_diff_index;
+        unsigned int min_diff_point;
+        neighbor_costs neighbors = {0.0f, 0.0f, INFINITY, -INFINITY, 0.0f, INFINITY};
+        for (i = 0; i < valid_len; i++) {
+            unsigned int diff_point = get_integral(integrals, w, x_end, y_end, diff_len, i)
+                - get_integral(integrals, w, x_begin, y_end, diff_len, i)
+                - get_integral(integrals, w, x_end, y_begin, diff_len, i)
+                + get_integral(integrals, w, x_begin, y_begin, diff_len, i);
+            if (i == 0 || min_diff_point > diff_point) {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            track_neighbor_costs(&neighbors, i, min_diff_index, diff_point);
+        }
+        return select_disparity(
+            min_disparity, min_diff_index, valid_len, min_diff_point, &neighbors, confidence);
+    }
+
+    // get_result_diffs from integral images.
+    __kernel void get_integral_result_diffs(
+                 __global unsigned int* integrals,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t result_w,
+                 size_t result_h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t result_x = get_global_id(0);
+        size_t result_y = get_global_id(1);
+        if (result_x >= result_w || result_y >= result_h)
+            return;
+        result_diffs[result_y * result_w + result_x] = find_integral_disparity(
+            integrals, w,
+            result_x * block_w, (result_x + 1) * block_w,
+            result_y * block_h, (result_y + 1) * block_h,
+            min_disparity, diff_len, confidences + result_y * result_w + result_x);
+    }
+
+    // get_dense_result_diffs from integral images.
+    __kernel void get_dense_integral_result_diffs(
+                 __global unsigned int* integrals,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        size_t x_begin = x < block_w / 2 ? 0 : x - block_w / 2;
+        size_t y_begin = y < block_h / 2 ? 0 : y - block_h / 2;
+        size_t x_end = min(x + block_w - block_w / 2, w);
+        size_t y_end = min(y + block_h - block_h / 2, h);
+        result_diffs[y * w + x] = find_integral_disparity(
+            integrals, w, x_begin, x_end, y_begin, y_end, min_disparity, diff_len,
+            confidences + y * w + x);
+    }
+
+    // Marks results invalid whose block of the right image has a gray level
+    // variance below threshold, with no confidence. Blocks are centered on
+    // each pixel when there is a result per pixel, and tiles otherwise.
+    __kernel void reject_textureless(
+                 __global uchar* gray_pixels,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t result_w,
+                 size_t result_h,
+                 unsigned int threshold) {
+        size_t result_x = get_global_id(0);
+        size_t result_y = get_global_id(1);
+        if (result_x >= result_w || result_y >= result_h)
+            return;
+        size_t x_begin, x_end, y_begin, y_end;
+        if (result_w == w && result_h == h) {
+            x_begin = result_x < block_w / 2 ? 0 : result_x - block_w / 2;
+            y_begin = result_y < block_h / 2 ? 0 : result_y - block_h / 2;
+            x_end = min(result_x + block_w - block_w / 2, w);
+            y_end = min(result_y + block_h - block_h / 2, h);
+        } else {
+            x_begin = result_x * block_w;
+            y_begin = result_y * block_h;
+            x_end = x_begin + block_w;
+            y_end = y_begin + block_h;
+        }
+        ulong sum = 0, square_sum = 0;
+        size_t x, y;
+        for (x = x_begin; x < x_end; x++) {
+            for (y = y_begin; y < y_end; y++) {
+                ulong pixel = gray_pixels[y * w + x];
+                sum += pixel;
+                square_sum += pixel * pixel;
+            }
+        }
+        // Both sides are n * n times the variance and the threshold.
+        ulong n = (x_end - x_begin) * (y_end - y_begin);
+        if (n * square_sum - sum * sum < (ulong) threshold * n * n) {
+            result_diffs[result_y * result_w + result_x] = INVALID_DISPARITY;
+    #if CONFIDENCE_MEASURE != CONFIDENCE_NONE
+            confidences[result_y * result_w + result_x] = 0.0f;
+    #endif
+        }
+    }
+
+    // Median of the valid results in the MEDIAN_SIZE square around each
+    // valid one, clipped to the map. Invalid results stay invalid. Built
+    // only when MEDIAN_SIZE sizes its window.
+    #ifdef MEDIAN_SIZE
+    __kernel void median_filter(
+                 __global DISPARITY_T* result_diffs,
+                 __global DISPARITY_T* filtered_diffs,
+                 size_t result_w,
+                 size_t result_h) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= result_w || y >= result_h)
+            return;
+        size_t index = y * result_w + x;
+        filtered_diffs[index] = result_diffs[index];
+        if (result_diffs[index] == INVALID_DISPARITY)
+            return;
+        size_t x_begin = x < MEDIAN_SIZE / 2 ? 0 : x - MEDIAN_SIZE / 2;
+        size_t y_begin = y < MEDIAN_SIZE / 2 ? 0 : y - MEDIAN_SIZE / 2;
+        size_t x_end = min(x + MEDIAN_SIZE / 2 + 1, result_w);
+        size_t y_end = min(y + MEDIAN_SIZE / 2 + 1, result_h);
+        DISPARITY_T window[MEDIAN_SIZE * MEDIAN_SIZE];
+        size_t n = 0, window_x, window_y, i;
+        for (window_y = y_begin; window_y < y_end; window_y++) {
+            for (window_x = x_begin; window_x < x_end; window_x++) {
+                DISPARITY_T disparity = result_diffs[window_y * result_w + window_x];
+                if (disparity == INVALID_DISPARITY)
+                    continue;
+                // Insertion keeps the window sorted.
+                for (i = n; i > 0 && window[i - 1] > disparity; i--)
+                    window[i] = window[i - 1];
+                window[i] = disparity;
+                n++;
+            }
+        }
+        filtered_diffs[index] = window[n / 2];
+    }
+    #endif
+
+    // Whether two results belong to the same region for speckle filtering.
+    int is_connected(DISPARITY_T a, DISPARITY_T b, float max_difference) {
+        return a != INVALID_DISPARITY && b != INVALID_DISPARITY && fabs((float) a - (float) b) <= max_difference;
+    }
+
+    // Lowers the label of each valid result, initially its own index, to the
+    // smallest of its connected neighbors and follows labels from there.
+    // Once nothing changes, every region is labeled with its smallest index.
+    __kernel void propagate_speckle_labels(
+                 __global DISPARITY_T* result_diffs,
+                 __global unsigned int* labels,
+                 __global unsigned int* changed,
+                 size_t result_w,
+                 size_t result_h,
+                 float max_difference) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= result_w || y >= result_h)
+            return;
+        size_t index = y * result_w + x;
+        DISPARITY_T disparity = result_diffs[index];
+        if (disparity == INVALID_DISPARITY)
+            return;
+        unsigned int label = labels[index];
+        if (x > 0 && is_connected(disparity, result_diffs[index - 1], max_difference))
+            label = min(label, labels[index - 1]);
+        if (x + 1 < result_w && is_connected(disparity, result_diffs[index + 1], max_difference))
+            label = min(label, labels[index + 1]);
+        if (y > 0 && is_connected(disparity, result_diffs[index - result_w], max_difference))
+            label = min(label, labels[index - result_w]);
+        if (y + 1 < result_h && is_connected(disparity, result_diffs[index + result_w], max_difference))
+            label = min(label, labels[index + result_w]);
+        // A label is the index of a result in the same region.
+        while (labels[label] < label)
+            label = labels[label];
+        if (label < labels[index]) {
+            labels[index] = label;
+            *changed = 1;
+        }
+    }
+
+    __kernel void count_speckle_sizes(
+                 __global DISPARITY_T* result_diffs,
+                 __global unsigned int* labels,
+                 __global unsigned int* sizes,
+                 size_t result_w,
+                 size_t result_h) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= result_w || y >= result_h)
+            return;
+        size_t index = y * result_w + x;
+        if (result_diffs[index] != INVALID_DISPARITY)
+            atomic_inc(&sizes[labels[index]]);
+    }
+
+    // Marks results invalid whose region holds at most max_size of them.
+    __kernel void remove_speckles(
+                 __global DISPARITY_T* result_diffs,
+                 __global unsigned int* labels,
+                 __global unsigned int* sizes,
+                 size_t result_w,
+                 size_t result_h,
+                 unsigned int max_size) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= result_w || y >= result_h)
+            return;
+        size_t index = y * result_w + x;
+        if (result_diffs[index] != INVALID_DISPARITY && sizes[labels[index]] <= max_size)
+            result_diffs[index] = INVALID_DISPARITY;
+    }
+
+    // Running sums of every cost slice along each row, and of its costs
+    // times the guide, one work item per disparity and row. The work item
+    // past the last disparity sums the guide and its squares instead, two
+    // planes per pixel.
+    __kernel void get_guided_row_integrals(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global uchar* guide_pixels,
+                 __global ulong* cost_integrals,
+                 __global ulong* guided_cost_integrals,
+                 __global ulong* guide_integrals,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t i = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (i > diff_len || y >= h)
+            return;
+        size_t x;
+        ulong sum = 0, guided_sum = 0;
+        for (x = 0; x < w; x++) {
+            ulong guide = guide_pixels[y * w + x];
+            ulong value = i == diff_len
+                ? guide
+                : get_cost(left_pixels, right_pixels, diffs, w, x, y, min_disparity, diff_len, i);
+            sum += value;
+            guided_sum += guide * value;
+            if (i == diff_len) {
+                guide_integrals[(y * w + x) * 2] = sum;
+                guide_integrals[(y * w + x) * 2 + 1] = guided_sum;
+            } else {
+                cost_integrals[(y * w + x) * diff_len + i] = sum;
+                guided_cost_integrals[(y * w + x) * diff_len + i] = guided_sum;
+            }
+        }
+    }
+
+    // Adds the guided filter's row integrals down each column in place, as
+    // accumulate_column_integrals does for the cost volume. In 64 bits their
+    // window sums are exact.
+    __kernel void accumulate_guided_column_integrals(
+                 __global ulong* cost_integrals,
+                 __global ulong* guided_cost_integrals,
+                 __global ulong* guide_integrals,
+                 size_t w,
+                 size_t h,
+                 size_t diff_len) {
+        size_t i = get_global_id(0);
+        size_t x = get_global_id(1);
+        if (i > diff_len || x >= w)
+            return;
+        size_t y;
+        for (y = 1; y < h; y++) {
+            if (i == diff_len) {
+                guide_integrals[(y * w + x) * 2] += guide_integrals[((y - 1) * w + x) * 2];
+                guide_integrals[(y * w + x) * 2 + 1] += guide_integrals[((y - 1) * w + x) * 2 + 1];
+            } else {
+                cost_integrals[(y * w + x) * diff_len + i] += cost_integrals[((y - 1) * w + x) * diff_len + i];
+                guided_cost_integrals[(y * w + x) * diff_len + i] +=
+                    guided_cost_integrals[((y - 1) * w + x) * diff_len + i];
+            }
+        }
+    }
+
+    // Sum of plane i over [x_begin, x_end) x [y_begin, y_end), from integral
+    // images that interleave planes values per pixel.
+    long get_guided_window_sum(
+                 __global ulong* integrals,
+                 size_t w,
+                 size_t planes,
+                 size_t x_begin,
+                 size_t x_end,
+                 size_t y_begin,
+                 size_t y_end,
+                 size_t i) {
+        ulong sum = integrals[((y_end - 1) * w + x_end - 1) * planes + i];
+        if (x_begin > 0)
+            sum -= integrals[((y_end - 1) * w + x_begin - 1) * planes + i];
+        if (y_begin > 0)
+            sum -= integrals[((y_begin - 1) * w + x_end - 1) * planes + i];
+        if (x_begin > 0 && y_begin > 0)
+            sum += integrals[((y_begin - 1) * w + x_begin - 1) * planes + i];
+        return (long) sum;
+    }
+
+    // Guided filter coefficients a and b of every cost slice over the block
+    // centered on (x, y), with the reference image as guide. Window sums
+    // come exact from the integral images whatever the block size; n scales
+    // them so that differences stay exact too.
+    __kernel void get_guided_coefficients(
+                 __global ulong* cost_integrals,
+                 __global ulong* guided_cost_integrals,
+                 __global ulong* guide_integrals,
+                 __global float* coefficients_a,
+                 __global float* coefficients_b,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t diff_len,
+                 float epsilon) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        size_t x_begin = x < block_w / 2 ? 0 : x - block_w / 2;
+        size_t y_begin = y < block_h / 2 ? 0 : y - block_h / 2;
+        size_t x_end = min(x + block_w - block_w / 2, w);
+        size_t y_end = min(y + block_h - block_h / 2, h);
+        long n = (x_end - x_begin) * (y_end - y_begin);
+        size_t i;
+        long guide_sum = get_guided_window_sum(guide_integrals, w, 2, x_begin, x_end, y_begin, y_end, 0);
+        long guide_square_sum = get_guided_window_sum(guide_integrals, w, 2, x_begin, x_end, y_begin, y_end, 1);
+        float variance = (float) (n * guide_square_sum - guide_sum * guide_sum) / (float) (n * n);
+        float guide_mean = (float) guide_sum / (float) n;
+        for (i = 0; i < diff_len; i++) {
+            long cost_sum = get_guided_window_sum(cost_integrals, w, diff_len, x_begin, x_end, y_begin, y_end, i);
+            long guided_cost_sum =
+                get_guided_window_sum(guided_cost_integrals, w, diff_len, x_begin, x_end, y_begin, y_end, i);
+            float covariance = (float) (n * guided_cost_sum - guide_sum * cost_sum) / (float) (n * n);
+            float a = covariance / (variance + epsilon);
+            coefficients_a[(y * w + x) * diff_len + i] = a;
+            coefficients_b[(y * w + x) * diff_len + i] = (float) cost_sum / (float) n - a * guide_mean;
+        }
+    }
+
+    // Sums of a and b of every cost slice along the row of the block
+    // centered on (x, y), which get_guided_result_diffs adds down its column.
+    __kernel void get_guided_row_sums(
+                 __global float* coefficients_a,
+                 __global float* coefficients_b,
+                 __global float* row_sums_a,
+                 __global float* row_sums_b,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        size_t x_begin = x < block_w / 2 ? 0 : x - block_w / 2;
+        size_t x_end = min(x + block_w - block_w / 2, w);
+        size_t i, block_x;
+        for (i = 0; i < diff_len; i++) {
+            float a_sum = 0.0f, b_sum = 0.0f;
+            for (block_x = x_begin; block_x < x_end; block_x++) {
+                a_sum += coefficients_a[(y * w + block_x) * diff_len + i];
+                b_sum += coefficients_b[(y * w + block_x) * diff_len + i];
+            }
+            row_sums_a[(y * w + x) * diff_len + i] = a_sum;
+            row_sums_b[(y * w + x) * diff_len + i] = b_sum;
+        }
+    }
+
+    // Disparity whose filtered cost, the block means of a and b applied to
+    // the guide at (x, y), is the smallest. The means add up the row sums
+    // down the block, in time linear in its width plus its height.
+    __kernel void get_guided_result_diffs(
+                 __global uchar* guide_pixels,
+                 __global float* row_sums_a,
+                 __global float* row_sums_b,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t block_w,
+                 size_t block_h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        size_t x_begin = x < block_w / 2 ? 0 : x - block_w / 2;
+        size_t y_begin = y < block_h / 2 ? 0 : y - block_h / 2;
+        size_t x_end = min(x + block_w - block_w / 2, w);
+        size_t y_end = min(y + block_h - block_h / 2, h);
+        size_t valid_len = diff_len;
+    #if BORDER_POLICY == BORDER_INVALID
+        if (x_end + min_disparity > w) {
+            result_diffs[y * w + x] = INVALID_DISPARITY;
+            return;
+        }
+        if (w - x_end - min_disparity + 1 < valid_len)
+            valid_len = w - x_end - min_disparity + 1;
+    #endif
+        float n = (float) ((x_end - x_begin) * (y_end - y_begin));
+        float guide = guide_pixels[y * w + x];
+        size_t i, block_y;
+        size_t min_diff_index;
+        float min_diff_point;
+        neighbor_costs neighbors = {0.0f, 0.0f, INFINITY, -INFINITY, 0.0f, INFINITY};
+        for (i = 0; i < valid_len; i++) {
+            float a_sum = 0.0f, b_sum = 0.0f;
+            for (block_y = y_begin; block_y < y_end; block_y++) {
+                a_sum += row_sums_a[(block_y * w + x) * diff_len + i];
+                b_sum += row_sums_b[(block_y * w + x) * diff_len + i];
+            }
+            float diff_point = a_sum / n * guide + b_sum / n;
+            if (i == 0 || min_diff_point > diff_point) {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            track_neighbor_costs(&neighbors, i, min_diff_index, diff_point);
+        }
+        result_diffs[y * w + x] = select_disparity(
+            min_disparity, min_diff_index, valid_len, min_diff_point, &neighbors, confidences + y * w + x);
+    }
+
+    #define ARM_LEFT 0
+    #define ARM_RIGHT 1
+    #define ARM_UP 2
+    #define ARM_DOWN 3
+
+    // Whether every channel of pixels a and b differs by less than threshold.
+    int is_similar_color(
+                 __global uchar* colors,
+                 size_t channels,
+                 size_t a,
+                 size_t b,
+                 uint threshold) {
+        size_t c;
+        for (c = 0; c < channels; c++) {
+            if (abs_diff(colors[a * channels + c], colors[b * channels + c]) >= threshold)
+                return 0;
+        }
+        return 1;
+    }
+
+    // Arm lengths of the cross at each right pixel, left, right, up and down:
+    // how far similar colors extend, up to max_arm_length.
+    __kernel void get_cross_arms(
+                 __global uchar* colors,
+                 __global unsigned short* arms,
+                 size_t channels,
+                 size_t w,
+                 size_t h,
+                 uint threshold,
+                 size_t max_arm_length) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        size_t center = y * w + x;
+        size_t n;
+        for (n = 0; n < max_arm_length && n < x
+                 && is_similar_color(colors, channels, center, center - n - 1, threshold); n++);
+        arms[center * 4 + ARM_LEFT] = n;
+        for (n = 0; n < max_arm_length && x + n + 1 < w
+                 && is_similar_color(colors, channels, center, center + n + 1, threshold); n++);
+        arms[center * 4 + ARM_RIGHT] = n;
+        for (n = 0; n < max_arm_length && n < y
+                 && is_similar_color(colors, channels, center, center - (n + 1) * w, threshold); n++);
+        arms[center * 4 + ARM_UP] = n;
+        for (n = 0; n < max_arm_length && y + n + 1 < h
+                 && is_similar_color(colors, channels, center, center + (n + 1) * w, threshold); n++);
+        arms[center * 4 + ARM_DOWN] = n;
+    }
+
+    // Cost of disparity min_disparity + i summed along the horizontal arm
+    // of (x, y).
+    unsigned int get_cross_row_cost(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* diffs,
+                 __global unsigned short* arms,
+                 size_t w,
+                 size_t x,
+                 size_t y,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 size_t i) {
+        size_t center = y * w + x;
+        size_t row_x;
+        unsigned int diff_point = 0;
+        for (row_x = x - arms[center * 4 + ARM_LEFT]; row_x <= x + arms[center * 4 + ARM_RIGHT]; row_x++)
+            diff_point += get_cost(left_pixels, right_pixels, diffs, w, row_x, y, min_disparity, diff_len, i);
+        return diff_point;
+    }
+
+    // Horizontal pass of cross aggregation: every cost summed along its
+    // pixel's horizontal arm, in the cost volume layout.
+    __kernel void get_cross_row_costs(
+                 __global unsigned short* diffs,
+                 __global unsigned short* arms,
+                 __global unsigned int* row_costs,
+                 size_t w,
+                 size_t h,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        size_t i;
+        for (i = 0; i < diff_len; i++)
+            row_costs[(y * w + x) * diff_len + i] = get_cross_row_cost(0, 0, diffs, arms, w, x, y, 0, diff_len, i);
+    }
+
+    // Vertical pass of cross aggregation: the disparity whose row costs,
+    // summed along the vertical arm of (x, y), are the smallest. Without
+    // row_costs, the rows are summed from the costs directly.
+    DISPARITY_T find_cross_disparity(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned int* row_costs,
+                 __global unsigned short* arms,
+                 size_t w,
+                 size_t x,
+                 size_t y,
+                 size_t min_disparity,
+                 size_t diff_len,
+                 __global float* confidence) {
+        size_t y_begin = y - arms[(y * w + x) * 4 + ARM_UP];
+        size_t y_end = y + arms[(y * w + x) * 4 + ARM_DOWN] + 1;
+        size_t i, region_y;
+        size_t valid_len = diff_len;
+    #if BORDER_POLICY == BORDER_INVALID
+        // The widest row of the region decides, as for a block.
+        size_t x_end = x + 1;
+        for (region_y = y_begin; region_y < y_end; region_y++)
+            x_end = max(x_end, x + arms[(region_y * w + x) * 4 + ARM_RIGHT] + 1);
+        if (x_end + min_disparity > w)
+            return INVALID_DISPARITY;
+        if (w - x_end - min_disparity + 1 < valid_len)
+            valid_len = w - x_end - min_disparity + 1;
+    #endif
+        size_t min_diff_index;
+        unsigned int min_diff_point;
+        neighbor_costs neighbors = {0.0f, 0.0f, INFINITY, -INFINITY, 0.0f, INFINITY};
+        for (i = 0; i < valid_len; i++) {
+            unsigned int diff_point = 0;
+            for (region_y = y_begin; region_y < y_end; region_y++) {
+                if (row_costs)
+                    diff_point += row_costs[(region_y * w + x) * diff_len + i];
+                else
+                    diff_point += get_cross_row_cost(
+                        left_pixels, right_pixels, 0, arms, w, x, region_y, min_disparity, diff_len, i);
+            }
+            if (i == 0 || min_diff_point > diff_point) {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            track_neighbor_costs(&neighbors, i, min_diff_index, diff_point);
+        }
+        return select_disparity(
+            min_disparity, min_diff_index, valid_len, min_diff_point, &neighbors, confidence);
+    }
+
+    __kernel void get_cross_result_diffs(
+                 __global unsigned int* row_costs,
+                 __global unsigned short* arms,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        result_diffs[y * w + x] = find_cross_disparity(
+            0, 0, row_costs, arms, w, x, y, min_disparity, diff_len, confidences + y * w + x);
+    }
+
+    // get_cross_result_diffs without a cost volume.
+    __kernel void get_streamed_cross_result_diffs(
+                 __global PIXEL_T* left_pixels,
+                 __global PIXEL_T* right_pixels,
+                 __global unsigned short* arms,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity,
+                 size_t diff_len) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        result_diffs[y * w + x] = find_cross_disparity(
+            left_pixels, right_pixels, 0, arms, w, x, y, min_disparity, diff_len,
+            confidences + y * w + x);
+    }
+
+    // SGM kernels, built only when DIFF_LEN lays out their path costs.
+    #ifdef DIFF_LEN
+    // Walks the SGM path along (dx, dy) that starts at (x, y) if the pixel
+    // before it is outside the image, and adds its path costs to aggregated.
+    // Each pixel lies on exactly one path per direction. Steps are at most
+    // 2, so at most two side columns and then two top or bottom rows start
+    // paths; each path keeps DIFF_LEN costs in path_costs in that order.
+    __kernel void aggregate_sgm_path(
+                 __global unsigned short* diffs,
+                 __global unsigned int* aggregated,
+                 __global unsigned int* path_costs,
+                 size_t w,
+                 size_t h,
+                 int dx,
+                 int dy,
+                 unsigned int p1,
+                 unsigned int p2) {
+        long x = get_global_id(0);
+        long y = get_global_id(1);
+        if (x >= (long) w || y >= (long) h)
+            return;
+        if (x - dx >= 0 && x - dx < (long) w && y - dy >= 0 && y - dy < (long) h)
+            return;
+        size_t path = x - dx < 0 || x - dx >= (long) w
+            ? y * (dx > 0 ? dx : -dx) + (dx > 0 ? x : w - 1 - x)
+            : 2 * h + (dy > 0 ? y : h - 1 - y) * w + x;
+        // Costs of the previous pixel on the path; zero before the first.
+        path_costs += path * DIFF_LEN;
+        unsigned int previous_min = 0;
+        size_t i;
+        for (i = 0; i < DIFF_LEN; i++)
+            path_costs[i] = 0;
+        for (; x >= 0 && x < (long) w && y >= 0 && y < (long) h; x += dx, y += dy) {
+            size_t index = (y * w + x) * DIFF_LEN;
+            unsigned int lower_previous = 0;
+            unsigned int current_min = 0;
+            for (i = 0; i < DIFF_LEN; i++) {
+                unsigned int previous = path_costs[i];
+                unsigned int cost = min(previous, previous_min + p2);
+                if (i > 0)
+                    cost = min(cost, lower_previous + p1);
+                if (i + 1 < DIFF_LEN)
+                    cost = min(cost, path_costs[i + 1] + p1);
+                lower_previous = previous;
+                path_costs[i] = diffs[index + i] + cost - previous_min;
+                aggregated[index + i] += path_costs[i];
+                if (i == 0 || current_min > path_costs[i])
+                    current_min = path_costs[i];
+            }
+            previous_min = current_min;
+        }
+    }
+
+    // Disparity with the smallest aggregated cost at each pixel.
+    __kernel void get_sgm_result_diffs(
+                 __global unsigned int* aggregated,
+                 __global DISPARITY_T* result_diffs,
+                 __global float* confidences,
+                 size_t w,
+                 size_t h,
+                 size_t min_disparity) {
+        size_t x = get_global_id(0);
+        size_t y = get_global_id(1);
+        if (x >= w || y >= h)
+            return;
+        size_t valid_len = DIFF_LEN;
+    #if BORDER_POLICY == BORDER_INVALID
+        if (x + 1 + min_disparity > w) {
+            result_diffs[y * w + x] = INVALID_DISPARITY;
+            return;
+        }
+        if (w - x - min_disparity < valid_len)
+            valid_len = w - x - min_disparity;
+    #endif
+        size_t index = (y * w + x) * DIFF_LEN;
+        size_t i;
+        size_t min_diff_index;
+        unsigned int min_diff_point;
+        neighbor_costs neighbors = {0.0f, 0.0f, INFINITY, -INFINITY, 0.0f, INFINITY};
+        for (i = 0; i < valid_len; i++) {
+            unsigned int diff_point = aggregated[index + i];
+            if (i == 0 || min_diff_point > diff_point) {
+                min_diff_index = i;
+                min_diff_point = diff_point;
+            }
+            track_neighbor_costs(&neighbors, i, min_diff_index, diff_point);
+        }
+        result_diffs[y * w + x] = select_disparity(
+            min_disparity, min_diff_index, valid_len, min_diff_point, &neighbors, confidences + y * w + x);
+    }
+    #endif
+"#;
+
+/// Kernel source specialized by `#define NAME VALUE` lines.
+pub fn source(defines: &[(&str, String)]) -> String {
+    let mut src = String::new();
+    for &(name, ref value) in defines {
+        src.push_str(&format!("#define {} {}\n", name, value));
+    }
+    src.push_str(SRC);
+    src
+}
@@ -0,0 +1,56 @@
+//! Stereo block matching on OpenCL, or on the CPU where OpenCL is unavailable.
+//!
+//! ```no_run
+//! use block_matching::{ColorMap, OutputFormat, StereoMatcher, StereoPair};
+//!
+//! # fn run() -> block_matching::Result<()> {
+//! let pair = StereoPair::open("../data/left.png", "../data/right.png")?;
+//! let disparity = StereoMatcher::new().block_size(11, 11).compute(&pair)?;
+//! disparity.save("result.png", OutputFormat::Image, ColorMap::Hue)?;
+//! # Ok(())
+//! # }
+//! ```
+
+extern crate image;
+#[cfg(feature = "opencl")]
+extern crate ocl;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+mod aggregation;
+mod backend;
+mod border;
+mod confidence;
+mod consistency;
+mod cost_function;
+mod cost_volume;
+mod cpu;
+mod density;
+mod disparity;
+mod dispatch;
+mod error;
+#[cfg(feature = "opencl")]
+mod kernels;
+mod matcher;
+#[cfg(feature = "opencl")]
+mod opencl;
+mod pair;
+mod render;
+mod sgm_paths;
+mod subpixel;
+
+pub use aggregation::Aggregation;
+pub use backend::Backend;
+pub use border::BorderPolicy;
+pub use confidence::{ConfidenceMap, ConfidenceMeasure};
+pub use cost_function::CostFunction;
+pub use cost_volume::CostVolume;
+pub use density::Density;
+pub use disparity::{DisparityData, DisparityMap, DisparityType, OutputFormat};
+pub use dispatch::DispatchStrategy;
+pub use error::{MatchError, Result};
+pub use matcher::StereoMatcher;
+pub use pair::{SizePolicy, StereoPair};
+pub use render::ColorMap;
+pub use sgm_paths::SgmPaths;
+pub use subpixel::SubpixelFit;
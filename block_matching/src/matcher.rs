@@ -0,0 +1,513 @@
+use aggregation::Aggregation;
+use backend::Backend;
+use border::BorderPolicy;
+use confidence::ConfidenceMeasure;
+use consistency;
+use cost_function::CostFunction;
+use cost_volume::CostVolume;
+use density::Density;
+use disparity::{Disparity, DisparityMap, DisparityType};
+use dispatch::DispatchStrategy;
+use error::{MatchError, Result};
+use cpu;
+#[cfg(feature = "opencl")]
+use opencl;
+use pair::StereoPair;
+use sgm_paths::SgmPaths;
+use subpixel::SubpixelFit;
+
+/// Largest `median_filter` size, which bounds the window each kernel work
+/// item sorts.
+const MAX_MEDIAN_SIZE: usize = 11;
+
+/// Block matching settings. The right image is the reference and each of its
+/// blocks is searched for in the left image shifted by
+/// `min_disparity..max_disparity` pixels. Blocks are centered on every pixel
+/// unless `Density::Tiled` is chosen.
+#[derive(Clone, Debug)]
+pub struct StereoMatcher {
+    pub(crate) block_w: usize,
+    pub(crate) block_h: usize,
+    pub(crate) min_disparity: usize,
+    pub(crate) max_disparity: Option<usize>,
+    pub(crate) density: Density,
+    pub(crate) cost_function: CostFunction,
+    pub(crate) transform_window: (usize, usize),
+    pub(crate) aggregation: Aggregation,
+    pub(crate) sgm_paths: SgmPaths,
+    pub(crate) sgm_penalties: (u32, u32),
+    pub(crate) adaptive_weights: (f32, f32),
+    pub(crate) cross_arms: (u8, usize),
+    pub(crate) guided_filter_epsilon: f32,
+    pub(crate) backend: Backend,
+    pub(crate) cost_volume: CostVolume,
+    pub(crate) dispatch: DispatchStrategy,
+    pub(crate) border: BorderPolicy,
+    pub(crate) border_value: u8,
+    pub(crate) disparity_type: Option<DisparityType>,
+    pub(crate) subpixel_fit: SubpixelFit,
+    pub(crate) left_right_check: Option<f32>,
+    pub(crate) uniqueness_ratio: u32,
+    pub(crate) texture_threshold: u32,
+    pub(crate) confidence: Option<ConfidenceMeasure>,
+    pub(crate) median_size: usize,
+    pub(crate) speckle_filter: Option<(usize, f32)>,
+}
+
+impl StereoMatcher {
+    /// 11x11 blocks searched over a quarter of the image width.
+    pub fn new() -> StereoMatcher {
+        StereoMatcher {
+            block_w: 11,
+            block_h: 11,
+            min_disparity: 0,
+            max_disparity: None,
+            density: Density::default(),
+            cost_function: CostFunction::default(),
+            transform_window: (5, 5),
+            aggregation: Aggregation::default(),
+            sgm_paths: SgmPaths::default(),
+            sgm_penalties: (8, 32),
+            adaptive_weights: (30.0, 15.0),
+            cross_arms: (20, 17),
+            guided_filter_epsilon: 10.0,
+            backend: Backend::default(),
+            cost_volume: CostVolume::default(),
+            dispatch: DispatchStrategy::default(),
+            border: BorderPolicy::default(),
+            border_value: 0,
+            disparity_type: None,
+            subpixel_fit: SubpixelFit::default(),
+            left_right_check: None,
+            uniqueness_ratio: 0,
+            texture_threshold: 0,
+            confidence: None,
+            median_size: 0,
+            speckle_filter: None,
+        }
+    }
+
+    pub fn block_size(mut self, block_w: usize, block_h: usize) -> StereoMatcher {
+        self.block_w = block_w;
+        self.block_h = block_h;
+        self
+    }
+
+    pub fn min_disparity(mut self, min_disparity: usize) -> StereoMatcher {
+        self.min_disparity = min_disparity;
+        self
+    }
+
+    /// Exclusive upper bound of the searched disparities.
+    pub fn max_disparity(mut self, max_disparity: usize) -> StereoMatcher {
+        self.max_disparity = Some(max_disparity);
+        self
+    }
+
+    pub fn density(mut self, density: Density) -> StereoMatcher {
+        self.density = density;
+        self
+    }
+
+    pub fn cost_function(mut self, cost_function: CostFunction) -> StereoMatcher {
+        self.cost_function = cost_function;
+        self
+    }
+
+    /// Window of the census and rank transforms, 5x5 by default. It holds at
+    /// most 65 pixels for census so that the bits fit in a u64, and at most
+    /// 256 for rank so that ranks fit in a u8.
+    pub fn transform_window(mut self, window_w: usize, window_h: usize) -> StereoMatcher {
+        self.transform_window = (window_w, window_h);
+        self
+    }
+
+    /// Bits of a census bit string, one per window pixel but the center.
+    #[cfg(feature = "opencl")]
+    pub(crate) fn census_bits(&self) -> usize {
+        self.transform_window.0 * self.transform_window.1 - 1
+    }
+
+    pub fn aggregation(mut self, aggregation: Aggregation) -> StereoMatcher {
+        self.aggregation = aggregation;
+        self
+    }
+
+    pub fn sgm_paths(mut self, sgm_paths: SgmPaths) -> StereoMatcher {
+        self.sgm_paths = sgm_paths;
+        self
+    }
+
+    /// Extra SGM cost of a one-pixel disparity change between neighbors
+    /// (`p1`) and of a larger jump (`p2`, at least `p1`). 8 and 32 by
+    /// default, suited to SAD; scale them with the cost function's range.
+    pub fn sgm_penalties(mut self, p1: u32, p2: u32) -> StereoMatcher {
+        self.sgm_penalties = (p1, p2);
+        self
+    }
+
+    /// Falloff of `Aggregation::AdaptiveWeights` with RGB distance
+    /// (`gamma_color`) and with distance in pixels from the block center
+    /// (`gamma_proximity`). 30 and 15 by default.
+    pub fn adaptive_weights(mut self, gamma_color: f32, gamma_proximity: f32) -> StereoMatcher {
+        self.adaptive_weights = (gamma_color, gamma_proximity);
+        self
+    }
+
+    /// Arms of `Aggregation::Cross` stop before a pixel with a channel that
+    /// differs from the center by `color_threshold` or more, and after
+    /// `max_arm_length` pixels. 20 and 17 by default.
+    pub fn cross_arms(mut self, color_threshold: u8, max_arm_length: usize) -> StereoMatcher {
+        self.cross_arms = (color_threshold, max_arm_length);
+        self
+    }
+
+    /// Regularization of `Aggregation::Guided` in squared gray levels: guide
+    /// variations well below its square root are smoothed over, larger ones
+    /// kept as edges. 10 by default.
+    pub fn guided_filter_epsilon(mut self, epsilon: f32) -> StereoMatcher {
+        self.guided_filter_epsilon = epsilon;
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> StereoMatcher {
+        self.backend = backend;
+        self
+    }
+
+    pub fn cost_volume(mut self, cost_volume: CostVolume) -> StereoMatcher {
+        self.cost_volume = cost_volume;
+        self
+    }
+
+    pub fn dispatch(mut self, dispatch: DispatchStrategy) -> StereoMatcher {
+        self.dispatch = dispatch;
+        self
+    }
+
+    pub fn border(mut self, border: BorderPolicy) -> StereoMatcher {
+        self.border = border;
+        self
+    }
+
+    /// Left pixel value past the right edge for `BorderPolicy::Constant`.
+    pub fn border_value(mut self, border_value: u8) -> StereoMatcher {
+        self.border_value = border_value;
+        self
+    }
+
+    /// Element type of the result. By default the smallest integer type that
+    /// holds the disparity range, or f32 with a `SubpixelFit`.
+    pub fn disparity_type(mut self, disparity_type: DisparityType) -> StereoMatcher {
+        self.disparity_type = Some(disparity_type);
+        self
+    }
+
+    /// Fit refining disparities to fractions of a pixel, which needs f32
+    /// output. Disparities at either end of the searched range stay whole.
+    pub fn subpixel_fit(mut self, subpixel_fit: SubpixelFit) -> StereoMatcher {
+        self.subpixel_fit = subpixel_fit;
+        self
+    }
+
+    /// Also matches the left image against the right one and marks a
+    /// disparity invalid unless the left pixel it points to has a disparity
+    /// within `max_difference` of it. This removes most occluded pixels but
+    /// doubles the matching time, and needs dense output.
+    pub fn left_right_check(mut self, max_difference: f32) -> StereoMatcher {
+        self.left_right_check = Some(max_difference);
+        self
+    }
+
+    /// Marks a disparity invalid when another one, not next to it, ties with
+    /// it or costs less than `percent` percent more, as StereoSGBM's
+    /// uniqueness ratio does. 0, the default, keeps every disparity. ZNCC,
+    /// whose costs go below zero, does not support it.
+    pub fn uniqueness_ratio(mut self, percent: u32) -> StereoMatcher {
+        self.uniqueness_ratio = percent;
+        self
+    }
+
+    /// Marks a disparity invalid when the gray levels of the right image
+    /// over its block have a variance below `min_variance`, in squared gray
+    /// levels. 0, the default, keeps every disparity.
+    pub fn texture_threshold(mut self, min_variance: u32) -> StereoMatcher {
+        self.texture_threshold = min_variance;
+        self
+    }
+
+    /// Also produces a `ConfidenceMap` of the disparities by `measure`, for
+    /// weighting them downstream. Invalid disparities have no confidence.
+    pub fn confidence(mut self, measure: ConfidenceMeasure) -> StereoMatcher {
+        self.confidence = Some(measure);
+        self
+    }
+
+    /// Replaces each valid disparity by the median of the valid ones in the
+    /// `size`x`size` square around it, which flattens isolated spikes. The
+    /// size is odd and at most 11; 0, the default, leaves disparities as
+    /// they are.
+    pub fn median_filter(mut self, size: usize) -> StereoMatcher {
+        self.median_size = size;
+        self
+    }
+
+    /// After any left-right check and median filter, marks invalid every
+    /// region of at most `max_size` disparities whose neighbors differ by at
+    /// most `max_difference`, as OpenCV's filterSpeckles does.
+    pub fn speckle_filter(mut self, max_size: usize, max_difference: f32) -> StereoMatcher {
+        self.speckle_filter = Some((max_size, max_difference));
+        self
+    }
+
+    fn output_type(&self, max_disparity: usize) -> DisparityType {
+        match (self.disparity_type, self.subpixel_fit) {
+            (Some(disparity_type), _) => disparity_type,
+            (None, SubpixelFit::None) => DisparityType::fitting(max_disparity),
+            (None, _) => DisparityType::F32,
+        }
+    }
+
+    fn checked_max_disparity(&self, pair: &StereoPair) -> Result<usize> {
+        let max_disparity = self.max_disparity.unwrap_or(pair.width / 4);
+        if self.block_w == 0 || self.block_h == 0 || self.block_w > pair.width || self.block_h > pair.height {
+            return Err(MatchError::InvalidParameter(format!(
+                "{}x{} block does not fit in {}x{} image",
+                self.block_w, self.block_h, pair.width, pair.height
+            )));
+        }
+        let (window_w, window_h) = self.transform_window;
+        let max_window_len = if self.cost_function == CostFunction::Census { 65 } else { 256 };
+        if window_w == 0 || window_h == 0 || window_w * window_h > max_window_len {
+            return Err(MatchError::InvalidParameter(format!(
+                "{}x{} transform window must hold 1 to {} pixels for {}",
+                window_w, window_h, max_window_len, self.cost_function
+            )));
+        }
+        match self.aggregation {
+            Aggregation::Box => self.check_box()?,
+            Aggregation::Sgm => self.check_sgm()?,
+            Aggregation::AdaptiveWeights => self.check_adaptive_weights()?,
+            Aggregation::Cross => self.check_cross()?,
+            Aggregation::Guided => self.check_guided_filter()?,
+            Aggregation::IntegralBox => self.check_integral_box()?,
+        }
+        if self.uniqueness_ratio > 0 && self.cost_function == CostFunction::Zncc {
+            return Err(MatchError::InvalidParameter("uniqueness ratio does not support ZNCC".to_string()));
+        }
+        match self.confidence {
+            Some(ConfidenceMeasure::LeftRight) if self.density != Density::Dense => {
+                return Err(MatchError::InvalidParameter(format!(
+                    "left-right confidence does not support {} density", self.density
+                )));
+            }
+            Some(measure @ ConfidenceMeasure::Ratio) | Some(measure @ ConfidenceMeasure::Curvature)
+                if self.cost_function == CostFunction::Zncc =>
+            {
+                return Err(MatchError::InvalidParameter(format!("{} confidence does not support ZNCC", measure)));
+            }
+            _ => {}
+        }
+        if let Some(max_difference) = self.left_right_check {
+            if !(max_difference >= 0.0 && max_difference.is_finite()) {
+                return Err(MatchError::InvalidParameter(format!(
+                    "left-right check difference {} must be non-negative", max_difference
+                )));
+            }
+            if self.density != Density::Dense {
+                return Err(MatchError::InvalidParameter(format!(
+                    "left-right check does not support {} density", self.density
+                )));
+            }
+        }
+        if self.median_size != 0 && (self.median_size % 2 == 0 || self.median_size > MAX_MEDIAN_SIZE) {
+            return Err(MatchError::InvalidParameter(format!(
+                "median filter size {} must be odd and at most {}", self.median_size, MAX_MEDIAN_SIZE
+            )));
+        }
+        if let Some((_, max_difference)) = self.speckle_filter {
+            if !(max_difference >= 0.0 && max_difference.is_finite()) {
+                return Err(MatchError::InvalidParameter(format!(
+                    "speckle difference {} must be non-negative", max_difference
+                )));
+            }
+        }
+        if self.min_disparity >= max_disparity {
+            return Err(MatchError::InvalidParameter(format!(
+                "disparity range {}..{} is empty", self.min_disparity, max_disparity
+            )));
+        }
+        if max_disparity > pair.width {
+            return Err(MatchError::InvalidParameter(format!(
+                "max disparity {} exceeds image width {}", max_disparity, pair.width
+            )));
+        }
+        let disparity_type = self.output_type(max_disparity);
+        if self.subpixel_fit != SubpixelFit::None && disparity_type != DisparityType::F32 {
+            return Err(MatchError::InvalidParameter(format!(
+                "{} subpixel fit needs f32 output, not {}", self.subpixel_fit, disparity_type
+            )));
+        }
+        if max_disparity > disparity_type.max_disparity() {
+            return Err(MatchError::InvalidParameter(format!(
+                "max disparity {} does not fit in {} output; use a wider disparity type",
+                max_disparity, disparity_type
+            )));
+        }
+        Ok(max_disparity)
+    }
+
+    fn check_box(&self) -> Result<()> {
+        let max_block_cost = (self.block_w * self.block_h) as u64 * self.cost_function.max_pixel_cost();
+        if max_block_cost > u64::from(u32::MAX) {
+            return Err(MatchError::InvalidParameter(format!(
+                "{}x{} block costs overflow u32 with {}", self.block_w, self.block_h, self.cost_function
+            )));
+        }
+        Ok(())
+    }
+
+    /// Settings every aggregation but the box sums shares.
+    fn check_dense_per_pixel_aggregation(&self) -> Result<()> {
+        if self.density != Density::Dense {
+            return Err(MatchError::InvalidParameter(format!(
+                "{} aggregation does not support {} density", self.aggregation, self.density
+            )));
+        }
+        if self.cost_function == CostFunction::Zncc {
+            return Err(MatchError::InvalidParameter(format!(
+                "{} aggregation needs a per-pixel cost, which ZNCC lacks", self.aggregation
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_sgm(&self) -> Result<()> {
+        self.check_dense_per_pixel_aggregation()?;
+        let (p1, p2) = self.sgm_penalties;
+        if self.cost_volume == CostVolume::Streamed {
+            return Err(MatchError::InvalidParameter("SGM needs the stored cost volume".to_string()));
+        }
+        if p1 > p2 {
+            return Err(MatchError::InvalidParameter(format!("SGM penalty P1 {} exceeds P2 {}", p1, p2)));
+        }
+        // Each path adds at most the pixel cost plus P2.
+        let paths = self.sgm_paths.directions().len() as u64;
+        if paths * (self.cost_function.max_pixel_cost() + u64::from(p2)) > u64::from(u32::MAX) {
+            return Err(MatchError::InvalidParameter(format!(
+                "SGM costs overflow u32 with {} and P2 {} over {} paths", self.cost_function, p2, self.sgm_paths
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_adaptive_weights(&self) -> Result<()> {
+        self.check_dense_per_pixel_aggregation()?;
+        let (gamma_color, gamma_proximity) = self.adaptive_weights;
+        if !(gamma_color > 0.0 && gamma_color.is_finite() && gamma_proximity > 0.0 && gamma_proximity.is_finite()) {
+            return Err(MatchError::InvalidParameter(format!(
+                "adaptive weight falloffs {} and {} must be positive", gamma_color, gamma_proximity
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_cross(&self) -> Result<()> {
+        self.check_dense_per_pixel_aggregation()?;
+        let max_arm_length = self.cross_arms.1;
+        // Regions fit in a square of two arms and the center on each side.
+        let region_len = (2 * max_arm_length as u64 + 1).pow(2);
+        if region_len.saturating_mul(self.cost_function.max_pixel_cost()) > u64::from(u32::MAX) {
+            return Err(MatchError::InvalidParameter(format!(
+                "cross regions with {} pixel arms overflow u32 with {}", max_arm_length, self.cost_function
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_guided_filter(&self) -> Result<()> {
+        self.check_dense_per_pixel_aggregation()?;
+        let epsilon = self.guided_filter_epsilon;
+        if !(epsilon > 0.0 && epsilon.is_finite()) {
+            return Err(MatchError::InvalidParameter(format!("guided filter epsilon {} must be positive", epsilon)));
+        }
+        if self.cost_volume == CostVolume::Streamed {
+            return Err(MatchError::InvalidParameter("guided filter needs the stored cost volume".to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_integral_box(&self) -> Result<()> {
+        self.check_box()?;
+        if self.cost_function == CostFunction::Zncc {
+            return Err(MatchError::InvalidParameter("integral aggregation does not support ZNCC".to_string()));
+        }
+        if self.cost_volume == CostVolume::Streamed {
+            return Err(MatchError::InvalidParameter("integral aggregation needs the stored cost volume".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn compute(&self, pair: &StereoPair) -> Result<DisparityMap> {
+        let max_disparity = self.checked_max_disparity(pair)?;
+        match self.output_type(max_disparity) {
+            DisparityType::U8 => self.compute_as::<u8>(pair, max_disparity),
+            DisparityType::U16 => self.compute_as::<u16>(pair, max_disparity),
+            DisparityType::F32 => self.compute_as::<f32>(pair, max_disparity),
+        }
+    }
+
+    fn compute_as<T: Disparity>(&self, pair: &StereoPair, max_disparity: usize) -> Result<DisparityMap> {
+        let (mut disparity, backend) = self.match_as::<T>(self.backend, pair, max_disparity)?;
+        let left_right_confidence = self.confidence == Some(ConfidenceMeasure::LeftRight);
+        if left_right_confidence || self.left_right_check.is_some() {
+            let (mirrored_left, _) = self.match_as::<T>(backend, &pair.mirrored(), max_disparity)?;
+            if left_right_confidence {
+                disparity.confidence = Some(consistency::left_right_agreement(&disparity, &mirrored_left));
+            }
+            if let Some(max_difference) = self.left_right_check {
+                consistency::check_left_right(&mut disparity, &mirrored_left, max_difference);
+            }
+        }
+        // Filtered once, after the check, so that the mirrored match stays
+        // raw and the regions the check leaves behind count as speckles.
+        if self.median_size > 1 || self.speckle_filter.is_some() {
+            match backend {
+                #[cfg(feature = "opencl")]
+                Backend::OpenCl => opencl::post_process(self, &mut disparity)?,
+                _ => cpu::post_process::<T>(self, &mut disparity),
+            }
+            disparity.clear_invalid_confidence();
+        }
+        Ok(disparity)
+    }
+
+    /// Matches on `backend` and tells which one ran: `Backend::Auto` falls
+    /// back to the CPU when there is no OpenCL platform.
+    fn match_as<T: Disparity>(
+        &self,
+        backend: Backend,
+        pair: &StereoPair,
+        max_disparity: usize,
+    ) -> Result<(DisparityMap, Backend)> {
+        match backend {
+            #[cfg(feature = "opencl")]
+            Backend::Auto => match opencl::compute(self, pair, max_disparity, self.output_type(max_disparity)) {
+                Err(MatchError::Platform(_)) => Ok((cpu::compute::<T>(self, pair, max_disparity), Backend::Cpu)),
+                result => result.map(|disparity| (disparity, Backend::OpenCl)),
+            },
+            #[cfg(feature = "opencl")]
+            Backend::OpenCl => opencl::compute(self, pair, max_disparity, self.output_type(max_disparity))
+                .map(|disparity| (disparity, Backend::OpenCl)),
+            #[cfg(not(feature = "opencl"))]
+            Backend::OpenCl => Err(MatchError::Platform("built without the opencl feature".to_string())),
+            _ => Ok((cpu::compute::<T>(self, pair, max_disparity), Backend::Cpu)),
+        }
+    }
+}
+
+impl Default for StereoMatcher {
+    fn default() -> StereoMatcher {
+        StereoMatcher::new()
+    }
+}
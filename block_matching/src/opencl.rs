@@ -0,0 +1,981 @@
+use ocl::enums::{DeviceInfo, DeviceInfoResult};
+use ocl::{self, Buffer, Context, Device, Kernel, MemFlags, OclPrm, Platform, ProQue, Program, Queue, SpatialDims};
+use std::mem;
+
+use aggregation::Aggregation;
+use confidence::ConfidenceMap;
+use cost_function::CostFunction;
+use cost_volume::CostVolume;
+use density::Density;
+use disparity::{Disparity, DisparityMap, DisparityType};
+use dispatch::DispatchStrategy;
+use error::{MatchError, Result};
+use kernels;
+use matcher::StereoMatcher;
+use pair::StereoPair;
+
+/// What the matching kernels compare: gray levels, or census bit strings.
+trait Pixel: OclPrm {
+    /// OpenCL C name of the type.
+    const CL_TYPE: &'static str;
+
+    fn matching_buffers(pro_que: &ProQue, matcher: &StereoMatcher, pair: &StereoPair)
+        -> Result<(Buffer<Self>, Buffer<Self>)>;
+}
+
+impl Pixel for u8 {
+    const CL_TYPE: &'static str = "uchar";
+
+    fn matching_buffers(pro_que: &ProQue, matcher: &StereoMatcher, pair: &StereoPair)
+        -> Result<(Buffer<u8>, Buffer<u8>)> {
+        if matcher.cost_function == CostFunction::Rank {
+            return transformed_buffers(pro_que, matcher, pair, "rank_transform");
+        }
+        Ok((bytes_buffer(pro_que, &pair.left)?, bytes_buffer(pro_que, &pair.right)?))
+    }
+}
+
+impl Pixel for u32 {
+    const CL_TYPE: &'static str = "uint";
+
+    fn matching_buffers(pro_que: &ProQue, matcher: &StereoMatcher, pair: &StereoPair)
+        -> Result<(Buffer<u32>, Buffer<u32>)> {
+        transformed_buffers(pro_que, matcher, pair, "census_transform")
+    }
+}
+
+impl Pixel for u64 {
+    const CL_TYPE: &'static str = "ulong";
+
+    fn matching_buffers(pro_que: &ProQue, matcher: &StereoMatcher, pair: &StereoPair)
+        -> Result<(Buffer<u64>, Buffer<u64>)> {
+        transformed_buffers(pro_que, matcher, pair, "census_transform")
+    }
+}
+
+/// A `Disparity` the kernels can write.
+trait DeviceDisparity: Disparity + OclPrm {
+    /// OpenCL C name of the type.
+    const CL_TYPE: &'static str;
+
+    fn invalid_literal() -> String;
+}
+
+impl DeviceDisparity for u8 {
+    const CL_TYPE: &'static str = "uchar";
+
+    fn invalid_literal() -> String {
+        u8::INVALID.to_string()
+    }
+}
+
+impl DeviceDisparity for u16 {
+    const CL_TYPE: &'static str = "ushort";
+
+    fn invalid_literal() -> String {
+        u16::INVALID.to_string()
+    }
+}
+
+impl DeviceDisparity for f32 {
+    const CL_TYPE: &'static str = "float";
+
+    fn invalid_literal() -> String {
+        "-1.0f".to_string()
+    }
+}
+
+fn bytes_buffer(pro_que: &ProQue, pixels: &[u8]) -> Result<Buffer<u8>> {
+    Ok(Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write().copy_host_ptr())
+        .len(pixels.len())
+        .copy_host_slice(pixels)
+        .build()?)
+}
+
+/// Left and right images after `kernel_name`, a transform over the matcher's
+/// `transform_window`.
+fn transformed_buffers<P: Pixel>(pro_que: &ProQue, matcher: &StereoMatcher, pair: &StereoPair, kernel_name: &str)
+    -> Result<(Buffer<P>, Buffer<P>)> {
+    let (window_w, window_h) = matcher.transform_window;
+    let mut buffers = vec![];
+    for pixels in [&pair.left, &pair.right].iter() {
+        let pixels_buffer = bytes_buffer(pro_que, pixels)?;
+        let transformed_buffer: Buffer<P> = Buffer::builder()
+            .queue(pro_que.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(pair.width * pair.height)
+            .build()?;
+        let transform_kernel = pro_que.kernel_builder(kernel_name)
+            .arg(&pixels_buffer)
+            .arg(&transformed_buffer)
+            .arg(pair.width)
+            .arg(pair.height)
+            .arg(window_w)
+            .arg(window_h)
+            .global_work_size((pair.width, pair.height))
+            .build()?;
+
+        unsafe { transform_kernel.enq()?; }
+        buffers.push(transformed_buffer);
+    }
+    let right = buffers.pop().unwrap();
+    let left = buffers.pop().unwrap();
+    Ok((left, right))
+}
+
+/// Left and right colors for `Aggregation::AdaptiveWeights` and the values
+/// per pixel: interleaved RGB, or gray levels when the pair has no color.
+fn color_buffers(pro_que: &ProQue, pair: &StereoPair) -> Result<(Buffer<u8>, Buffer<u8>, usize)> {
+    match pair.color {
+        Some((ref left, ref right)) => Ok((bytes_buffer(pro_que, left)?, bytes_buffer(pro_que, right)?, 3)),
+        None => Ok((bytes_buffer(pro_que, &pair.left)?, bytes_buffer(pro_que, &pair.right)?, 1)),
+    }
+}
+
+/// Arms of the cross at every right pixel for `Aggregation::Cross`, four
+/// per pixel.
+fn cross_arms_buffer(pro_que: &ProQue, matcher: &StereoMatcher, pair: &StereoPair) -> Result<Buffer<u16>> {
+    let (threshold, max_arm_length) = matcher.cross_arms;
+    let (colors_buffer, channels) = match pair.color {
+        Some((_, ref right)) => (bytes_buffer(pro_que, right)?, 3usize),
+        None => (bytes_buffer(pro_que, &pair.right)?, 1),
+    };
+    let arms_buffer: Buffer<u16> = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(pair.width * pair.height * 4)
+        .build()?;
+    let arms_kernel = pro_que.kernel_builder("get_cross_arms")
+        .arg(&colors_buffer)
+        .arg(&arms_buffer)
+        .arg(channels)
+        .arg(pair.width)
+        .arg(pair.height)
+        .arg(u32::from(threshold))
+        .arg(max_arm_length)
+        .global_work_size((pair.width, pair.height))
+        .build()?;
+
+    unsafe { arms_kernel.enq()?; }
+    Ok(arms_buffer)
+}
+
+/// Integral images of the pixels and their squares for ZNCC's window means
+/// and variances, or a placeholder for the other costs.
+fn window_sums_buffer<P: Pixel>(
+    pro_que: &ProQue,
+    matcher: &StereoMatcher,
+    pair: &StereoPair,
+    (left_pixels_buffer, right_pixels_buffer): (&Buffer<P>, &Buffer<P>),
+    diff_len: usize,
+) -> Result<Buffer<u32>> {
+    if matcher.cost_function != CostFunction::Zncc {
+        return Ok(Buffer::builder()
+            .queue(pro_que.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(1)
+            .build()?);
+    }
+    // The left image is read past its right edge by every searched disparity.
+    let sums_w = pair.width + matcher.min_disparity + diff_len;
+    let window_sums_buffer: Buffer<u32> = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(sums_w * pair.height * 4)
+        .build()?;
+    let row_sums_kernel = pro_que.kernel_builder("get_row_window_sums")
+        .arg(left_pixels_buffer)
+        .arg(right_pixels_buffer)
+        .arg(&window_sums_buffer)
+        .arg(pair.width)
+        .arg(pair.height)
+        .arg(matcher.min_disparity)
+        .arg(diff_len)
+        .global_work_size(pair.height)
+        .build()?;
+    let column_sums_kernel = pro_que.kernel_builder("accumulate_column_window_sums")
+        .arg(&window_sums_buffer)
+        .arg(pair.width)
+        .arg(pair.height)
+        .arg(matcher.min_disparity)
+        .arg(diff_len)
+        .global_work_size((sums_w, 4))
+        .build()?;
+
+    unsafe {
+        row_sums_kernel.enq()?;
+        column_sums_kernel.enq()?;
+    }
+    Ok(window_sums_buffer)
+}
+
+/// Filters every cost slice of `diffs_buffer` and returns the kernel picking
+/// the disparities from them.
+fn guided_filter_kernel<T: DeviceDisparity, P: Pixel>(
+    pro_que: &ProQue,
+    matcher: &StereoMatcher,
+    pair: &StereoPair,
+    (left_pixels_buffer, right_pixels_buffer): (&Buffer<P>, &Buffer<P>),
+    diffs_buffer: &Buffer<u16>,
+    (result_diffs_buffer, confidences_buffer): (&Buffer<T>, &Buffer<f32>),
+    diff_len: usize,
+) -> Result<Kernel> {
+    let guide_buffer = bytes_buffer(pro_que, &pair.right)?;
+    let volume_len = pair.width * pair.height * diff_len;
+    let float_buffer = || -> Result<Buffer<f32>> {
+        Ok(Buffer::builder()
+            .queue(pro_que.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(volume_len)
+            .build()?)
+    };
+    let (coefficients_a_buffer, coefficients_b_buffer) = (float_buffer()?, float_buffer()?);
+
+    // The integral images are released before the row sums are allocated.
+    {
+        let mut integral_buffers = vec![];
+        for &len in [volume_len, volume_len, pair.width * pair.height * 2].iter() {
+            let integrals_buffer: Buffer<u64> = Buffer::builder()
+                .queue(pro_que.queue().clone())
+                .flags(MemFlags::new().read_write())
+                .len(len)
+                .build()?;
+            integral_buffers.push(integrals_buffer);
+        }
+        let (cost_integrals_buffer, guided_cost_integrals_buffer, guide_integrals_buffer) =
+            (&integral_buffers[0], &integral_buffers[1], &integral_buffers[2]);
+
+        let row_integrals_kernel = pro_que.kernel_builder("get_guided_row_integrals")
+            .arg(left_pixels_buffer)
+            .arg(right_pixels_buffer)
+            .arg(diffs_buffer)
+            .arg(&guide_buffer)
+            .arg(cost_integrals_buffer)
+            .arg(guided_cost_integrals_buffer)
+            .arg(guide_integrals_buffer)
+            .arg(pair.width)
+            .arg(pair.height)
+            .arg(matcher.min_disparity)
+            .arg(diff_len)
+            .global_work_size((diff_len + 1, pair.height))
+            .build()?;
+        let column_integrals_kernel = pro_que.kernel_builder("accumulate_guided_column_integrals")
+            .arg(cost_integrals_buffer)
+            .arg(guided_cost_integrals_buffer)
+            .arg(guide_integrals_buffer)
+            .arg(pair.width)
+            .arg(pair.height)
+            .arg(diff_len)
+            .global_work_size((diff_len + 1, pair.width))
+            .build()?;
+        let coefficients_kernel = pro_que.kernel_builder("get_guided_coefficients")
+            .arg(cost_integrals_buffer)
+            .arg(guided_cost_integrals_buffer)
+            .arg(guide_integrals_buffer)
+            .arg(&coefficients_a_buffer)
+            .arg(&coefficients_b_buffer)
+            .arg(pair.width)
+            .arg(pair.height)
+            .arg(matcher.block_w)
+            .arg(matcher.block_h)
+            .arg(diff_len)
+            .arg(matcher.guided_filter_epsilon)
+            .global_work_size((pair.width, pair.height))
+            .build()?;
+
+        unsafe {
+            row_integrals_kernel.enq()?;
+            column_integrals_kernel.enq()?;
+            coefficients_kernel.enq()?;
+        }
+    }
+
+    let (row_sums_a_buffer, row_sums_b_buffer) = (float_buffer()?, float_buffer()?);
+    let row_sums_kernel = pro_que.kernel_builder("get_guided_row_sums")
+        .arg(&coefficients_a_buffer)
+        .arg(&coefficients_b_buffer)
+        .arg(&row_sums_a_buffer)
+        .arg(&row_sums_b_buffer)
+        .arg(pair.width)
+        .arg(pair.height)
+        .arg(matcher.block_w)
+        .arg(diff_len)
+        .global_work_size((pair.width, pair.height))
+        .build()?;
+
+    unsafe { row_sums_kernel.enq()?; }
+
+    Ok(pro_que.kernel_builder("get_guided_result_diffs")
+        .arg(&guide_buffer)
+        .arg(&row_sums_a_buffer)
+        .arg(&row_sums_b_buffer)
+        .arg(result_diffs_buffer)
+        .arg(confidences_buffer)
+        .arg(pair.width)
+        .arg(pair.height)
+        .arg(matcher.block_w)
+        .arg(matcher.block_h)
+        .arg(matcher.min_disparity)
+        .arg(diff_len)
+        .global_work_size((pair.width, pair.height))
+        .build()?)
+}
+
+fn kernel_defines<T: DeviceDisparity, P: Pixel>(
+    matcher: &StereoMatcher,
+    diff_len: usize,
+) -> Vec<(&'static str, String)> {
+    let mut defines = vec![
+        ("BORDER_POLICY", matcher.border.kernel_value().to_string()),
+        ("BORDER_VALUE", matcher.border_value.to_string()),
+        ("COST_FUNCTION", matcher.cost_function.kernel_value().to_string()),
+        ("PIXEL_T", P::CL_TYPE.to_string()),
+        ("DISPARITY_T", T::CL_TYPE.to_string()),
+        ("INVALID_DISPARITY", T::invalid_literal()),
+        ("SUBPIXEL_FIT", matcher.subpixel_fit.kernel_value().to_string()),
+        ("UNIQUENESS_RATIO", matcher.uniqueness_ratio.to_string()),
+        ("CONFIDENCE_MEASURE", matcher.confidence.map_or(0, |measure| measure.kernel_value()).to_string()),
+    ];
+    if matcher.aggregation == Aggregation::Sgm {
+        defines.push(("DIFF_LEN", diff_len.to_string()));
+    }
+    if matcher.median_size > 1 {
+        defines.push(("MEDIAN_SIZE", matcher.median_size.to_string()));
+    }
+    defines
+}
+
+/// Runs the kernels on the first device of the first platform, writing
+/// `disparity_type` disparities.
+pub(crate) fn compute(
+    matcher: &StereoMatcher,
+    pair: &StereoPair,
+    max_disparity: usize,
+    disparity_type: DisparityType,
+) -> Result<DisparityMap> {
+    match disparity_type {
+        DisparityType::U8 => compute_as::<u8>(matcher, pair, max_disparity),
+        DisparityType::U16 => compute_as::<u16>(matcher, pair, max_disparity),
+        DisparityType::F32 => compute_as::<f32>(matcher, pair, max_disparity),
+    }
+}
+
+fn compute_as<T: DeviceDisparity>(
+    matcher: &StereoMatcher,
+    pair: &StereoPair,
+    max_disparity: usize,
+) -> Result<DisparityMap> {
+    match matcher.cost_function {
+        CostFunction::Census if matcher.census_bits() <= 32 => compute_with::<T, u32>(matcher, pair, max_disparity),
+        CostFunction::Census => compute_with::<T, u64>(matcher, pair, max_disparity),
+        _ => compute_with::<T, u8>(matcher, pair, max_disparity),
+    }
+}
+
+fn compute_with<T: DeviceDisparity, P: Pixel>(
+    matcher: &StereoMatcher,
+    pair: &StereoPair,
+    max_disparity: usize,
+) -> Result<DisparityMap> {
+    let width = pair.width;
+    let height = pair.height;
+    let block_w = matcher.block_w;
+    let block_h = matcher.block_h;
+    let min_disparity = matcher.min_disparity;
+    let diff_len = max_disparity - min_disparity;
+
+    let global_work_size = match matcher.dispatch {
+        DispatchStrategy::ThreeDims => SpatialDims::new(Some(width),Some(height),Some(diff_len)),
+        _ => SpatialDims::new(Some(width),Some(height),Some(1)),
+    }?;
+    let pro_que = build_pro_que(&kernels::source(&kernel_defines::<T, P>(matcher, diff_len)), global_work_size)?;
+
+    let (left_pixels_buffer, right_pixels_buffer) = P::matching_buffers(&pro_que, matcher, pair)?;
+
+    let (result_w, result_h) = match matcher.density {
+        Density::Dense => (width, height),
+        Density::Tiled => (width / block_w, height / block_h),
+    };
+
+    let result_diffs_buffer: Buffer<T> = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(result_w * result_h)
+        .build()?;
+
+    // Only measures taken from the costs are written by the kernels.
+    let cost_confidence = matcher.confidence.filter(|measure| measure.is_from_costs());
+    let confidences_buffer: Buffer<f32> = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(if cost_confidence.is_some() { result_w * result_h } else { 1 })
+        .fill_val(0.0)
+        .build()?;
+
+    let window_sums_buffer =
+        window_sums_buffer(&pro_que, matcher, pair, (&left_pixels_buffer, &right_pixels_buffer), diff_len)?;
+
+    let volume_size = (width * height * diff_len * mem::size_of::<u16>()) as u64;
+    let streamed = match matcher.cost_volume {
+        // SGM, integral images and the guided filter have no streamed form.
+        CostVolume::Auto => match matcher.aggregation {
+            Aggregation::Sgm => {
+                let aggregated_size = (width * height * diff_len * mem::size_of::<u32>()) as u64;
+                check_alloc_size(&pro_que, aggregated_size, "SGM aggregated costs")?;
+                false
+            }
+            Aggregation::IntegralBox => {
+                let integrals_size = (width * height * diff_len * mem::size_of::<u32>()) as u64;
+                check_alloc_size(&pro_que, integrals_size, "integral images")?;
+                false
+            }
+            Aggregation::Guided => {
+                let integrals_size = (width * height * diff_len * mem::size_of::<u64>()) as u64;
+                check_alloc_size(&pro_que, integrals_size, "guided filter integral images")?;
+                false
+            }
+            // Stored cross regions also sum each row into u32 costs.
+            Aggregation::Cross => {
+                let row_costs_size = (width * height * diff_len * mem::size_of::<u32>()) as u64;
+                row_costs_size > max_mem_alloc_size(&pro_que)?
+            }
+            _ => volume_size > max_mem_alloc_size(&pro_que)?,
+        },
+        CostVolume::Stored => false,
+        CostVolume::Streamed => true,
+    };
+
+    let (gamma_color, gamma_proximity) = matcher.adaptive_weights;
+
+    let get_result_diffs_kernel = if streamed {
+        match (matcher.aggregation, matcher.density) {
+            (Aggregation::AdaptiveWeights, _) => {
+                let (left_colors_buffer, right_colors_buffer, channels) = color_buffers(&pro_que, pair)?;
+                pro_que.kernel_builder("get_streamed_adaptive_result_diffs")
+                    .arg(&left_pixels_buffer)
+                    .arg(&right_pixels_buffer)
+                    .arg(&left_colors_buffer)
+                    .arg(&right_colors_buffer)
+                    .arg(&result_diffs_buffer)
+                    .arg(&confidences_buffer)
+                    .arg(channels)
+                    .arg(width)
+                    .arg(height)
+                    .arg(block_w)
+                    .arg(block_h)
+                    .arg(min_disparity)
+                    .arg(diff_len)
+                    .arg(gamma_color)
+                    .arg(gamma_proximity)
+                    .global_work_size((result_w, result_h))
+                    .build()?
+            }
+            (Aggregation::Cross, _) => {
+                let arms_buffer = cross_arms_buffer(&pro_que, matcher, pair)?;
+                pro_que.kernel_builder("get_streamed_cross_result_diffs")
+                    .arg(&left_pixels_buffer)
+                    .arg(&right_pixels_buffer)
+                    .arg(&arms_buffer)
+                    .arg(&result_diffs_buffer)
+                    .arg(&confidences_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(min_disparity)
+                    .arg(diff_len)
+                    .global_work_size((result_w, result_h))
+                    .build()?
+            }
+            (_, Density::Dense) => pro_que.kernel_builder("get_streamed_dense_result_diffs")
+                .arg(&left_pixels_buffer)
+                .arg(&right_pixels_buffer)
+                .arg(&window_sums_buffer)
+                .arg(&result_diffs_buffer)
+                .arg(&confidences_buffer)
+                .arg(width)
+                .arg(height)
+                .arg(block_w)
+                .arg(block_h)
+                .arg(min_disparity)
+                .arg(diff_len)
+                .global_work_size((result_w, result_h))
+                .build()?,
+            (_, Density::Tiled) => pro_que.kernel_builder("get_streamed_result_diffs")
+                .arg(&left_pixels_buffer)
+                .arg(&right_pixels_buffer)
+                .arg(&window_sums_buffer)
+                .arg(&result_diffs_buffer)
+                .arg(&confidences_buffer)
+                .arg(width)
+                .arg(height)
+                .arg(block_w)
+                .arg(block_h)
+                .arg(result_w)
+                .arg(result_h)
+                .arg(min_disparity)
+                .arg(diff_len)
+                .global_work_size((result_w, result_h))
+                .build()?,
+        }
+    } else {
+        let diffs_buffer: Buffer<u16> = Buffer::builder()
+            .queue(pro_que.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(width * height * diff_len)
+            .build()?;
+
+        match matcher.dispatch {
+            DispatchStrategy::ThreeDims => {
+                let get_diffs_kernel = pro_que.kernel_builder("get_diffs_3dims")
+                    .arg(&left_pixels_buffer)
+                    .arg(&right_pixels_buffer)
+                    .arg(&diffs_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(min_disparity)
+                    .arg(diff_len)
+                    .build()?;
+
+                unsafe { get_diffs_kernel.enq()?; }
+            }
+            DispatchStrategy::TwoDimsWithLoopInKernel => {
+                let get_diffs_kernel = pro_que.kernel_builder("get_diffs")
+                    .arg(&left_pixels_buffer)
+                    .arg(&right_pixels_buffer)
+                    .arg(&diffs_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(min_disparity)
+                    .arg(diff_len)
+                    .build()?;
+
+                unsafe { get_diffs_kernel.enq()?; }
+            }
+            DispatchStrategy::TwoDimsWithReplacingArg => {
+                let get_diffs_kernel = pro_que.kernel_builder("get_diffs_at_index")
+                    .arg(&left_pixels_buffer)
+                    .arg(&right_pixels_buffer)
+                    .arg(&diffs_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(min_disparity)
+                    .arg(diff_len)
+                    .arg_named("diff_index", 0usize)
+                    .build()?;
+
+                for i in 0..diff_len {
+                    get_diffs_kernel.set_arg("diff_index", i)?;
+                    unsafe { get_diffs_kernel.enq()?; }
+                }
+            }
+        }
+
+        match (matcher.aggregation, matcher.density) {
+            (Aggregation::Sgm, _) => {
+                let (p1, p2) = matcher.sgm_penalties;
+                let aggregated_buffer: Buffer<u32> = Buffer::builder()
+                    .queue(pro_que.queue().clone())
+                    .flags(MemFlags::new().read_write())
+                    .len(width * height * diff_len)
+                    .fill_val(0)
+                    .build()?;
+                // Paths enter through at most two columns and two rows.
+                let path_costs_buffer: Buffer<u32> = Buffer::builder()
+                    .queue(pro_que.queue().clone())
+                    .flags(MemFlags::new().read_write())
+                    .len(2 * (width + height) * diff_len)
+                    .build()?;
+
+                let aggregate_kernel = pro_que.kernel_builder("aggregate_sgm_path")
+                    .arg(&diffs_buffer)
+                    .arg(&aggregated_buffer)
+                    .arg(&path_costs_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg_named("dx", 0i32)
+                    .arg_named("dy", 0i32)
+                    .arg(p1)
+                    .arg(p2)
+                    .global_work_size((width, height))
+                    .build()?;
+
+                for &(dx, dy) in matcher.sgm_paths.directions() {
+                    aggregate_kernel.set_arg("dx", dx)?;
+                    aggregate_kernel.set_arg("dy", dy)?;
+                    unsafe { aggregate_kernel.enq()?; }
+                }
+
+                pro_que.kernel_builder("get_sgm_result_diffs")
+                    .arg(&aggregated_buffer)
+                    .arg(&result_diffs_buffer)
+                    .arg(&confidences_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(min_disparity)
+                    .global_work_size((width, height))
+                    .build()?
+            }
+            (Aggregation::AdaptiveWeights, _) => {
+                let (left_colors_buffer, right_colors_buffer, channels) = color_buffers(&pro_que, pair)?;
+                pro_que.kernel_builder("get_adaptive_result_diffs")
+                    .arg(&left_pixels_buffer)
+                    .arg(&right_pixels_buffer)
+                    .arg(&diffs_buffer)
+                    .arg(&left_colors_buffer)
+                    .arg(&right_colors_buffer)
+                    .arg(&result_diffs_buffer)
+                    .arg(&confidences_buffer)
+                    .arg(channels)
+                    .arg(width)
+                    .arg(height)
+                    .arg(block_w)
+                    .arg(block_h)
+                    .arg(min_disparity)
+                    .arg(diff_len)
+                    .arg(gamma_color)
+                    .arg(gamma_proximity)
+                    .global_work_size((result_w, result_h))
+                    .build()?
+            }
+            (Aggregation::Guided, _) => guided_filter_kernel(
+                &pro_que,
+                matcher,
+                pair,
+                (&left_pixels_buffer, &right_pixels_buffer),
+                &diffs_buffer,
+                (&result_diffs_buffer, &confidences_buffer),
+                diff_len,
+            )?,
+            (Aggregation::Cross, _) => {
+                let arms_buffer = cross_arms_buffer(&pro_que, matcher, pair)?;
+                let row_costs_buffer: Buffer<u32> = Buffer::builder()
+                    .queue(pro_que.queue().clone())
+                    .flags(MemFlags::new().read_write())
+                    .len(width * height * diff_len)
+                    .build()?;
+
+                let row_costs_kernel = pro_que.kernel_builder("get_cross_row_costs")
+                    .arg(&diffs_buffer)
+                    .arg(&arms_buffer)
+                    .arg(&row_costs_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(diff_len)
+                    .global_work_size((width, height))
+                    .build()?;
+
+                unsafe { row_costs_kernel.enq()?; }
+
+                pro_que.kernel_builder("get_cross_result_diffs")
+                    .arg(&row_costs_buffer)
+                    .arg(&arms_buffer)
+                    .arg(&result_diffs_buffer)
+                    .arg(&confidences_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(min_disparity)
+                    .arg(diff_len)
+                    .global_work_size((result_w, result_h))
+                    .build()?
+            }
+            (Aggregation::IntegralBox, density) => {
+                let integrals_buffer: Buffer<u32> = Buffer::builder()
+                    .queue(pro_que.queue().clone())
+                    .flags(MemFlags::new().read_write())
+                    .len(width * height * diff_len)
+                    .build()?;
+
+                let row_integrals_kernel = pro_que.kernel_builder("get_row_integrals")
+                    .arg(&diffs_buffer)
+                    .arg(&integrals_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(diff_len)
+                    .global_work_size((diff_len, height))
+                    .build()?;
+
+                unsafe { row_integrals_kernel.enq()?; }
+
+                let column_integrals_kernel = pro_que.kernel_builder("accumulate_column_integrals")
+                    .arg(&integrals_buffer)
+                    .arg(width)
+                    .arg(height)
+                    .arg(diff_len)
+                    .global_work_size((diff_len, width))
+                    .build()?;
+
+                unsafe { column_integrals_kernel.enq()?; }
+
+                match density {
+                    Density::Dense => pro_que.kernel_builder("get_dense_integral_result_diffs")
+                        .arg(&integrals_buffer)
+                        .arg(&result_diffs_buffer)
+                        .arg(&confidences_buffer)
+                        .arg(width)
+                        .arg(height)
+                        .arg(block_w)
+                        .arg(block_h)
+                        .arg(min_disparity)
+                        .arg(diff_len)
+                        .global_work_size((result_w, result_h))
+                        .build()?,
+                    Density::Tiled => pro_que.kernel_builder("get_integral_result_diffs")
+                        .arg(&integrals_buffer)
+                        .arg(&result_diffs_buffer)
+                        .arg(&confidences_buffer)
+                        .arg(width)
+                        .arg(height)
+                        .arg(block_w)
+                        .arg(block_h)
+                        .arg(result_w)
+                        .arg(result_h)
+                        .arg(min_disparity)
+                        .arg(diff_len)
+                        .global_work_size((result_w, result_h))
+                        .build()?,
+                }
+            }
+            (Aggregation::Box, Density::Dense) => pro_que.kernel_builder("get_dense_result_diffs")
+                .arg(&left_pixels_buffer)
+                .arg(&right_pixels_buffer)
+                .arg(&diffs_buffer)
+                .arg(&window_sums_buffer)
+                .arg(&result_diffs_buffer)
+                .arg(&confidences_buffer)
+                .arg(width)
+                .arg(height)
+                .arg(block_w)
+                .arg(block_h)
+                .arg(min_disparity)
+                .arg(diff_len)
+                .global_work_size((result_w, result_h))
+                .build()?,
+            (Aggregation::Box, Density::Tiled) => pro_que.kernel_builder("get_result_diffs")
+                .arg(&left_pixels_buffer)
+                .arg(&right_pixels_buffer)
+                .arg(&diffs_buffer)
+                .arg(&window_sums_buffer)
+                .arg(&result_diffs_buffer)
+                .arg(&confidences_buffer)
+                .arg(width)
+                .arg(height)
+                .arg(block_w)
+                .arg(block_h)
+                .arg(result_w)
+                .arg(result_h)
+                .arg(min_disparity)
+                .arg(diff_len)
+                .global_work_size((result_w, result_h))
+                .build()?,
+        }
+    };
+
+    unsafe { get_result_diffs_kernel.enq()?; }
+
+    if matcher.texture_threshold > 0 {
+        let gray_buffer = bytes_buffer(&pro_que, &pair.right)?;
+        let texture_kernel = pro_que.kernel_builder("reject_textureless")
+            .arg(&gray_buffer)
+            .arg(&result_diffs_buffer)
+            .arg(&confidences_buffer)
+            .arg(width)
+            .arg(height)
+            .arg(block_w)
+            .arg(block_h)
+            .arg(result_w)
+            .arg(result_h)
+            .arg(matcher.texture_threshold)
+            .global_work_size((result_w, result_h))
+            .build()?;
+
+        unsafe { texture_kernel.enq()?; }
+    }
+
+    let mut result_diffs = vec![T::default(); result_diffs_buffer.len()];
+    result_diffs_buffer.read(&mut result_diffs).enq()?;
+
+    let confidence = match cost_confidence {
+        Some(measure) => {
+            let mut confidences = vec![0.0; confidences_buffer.len()];
+            confidences_buffer.read(&mut confidences).enq()?;
+            Some(ConfidenceMap { width: result_w, height: result_h, measure, data: confidences })
+        }
+        None => None,
+    };
+
+    Ok(DisparityMap {
+        width: result_w,
+        height: result_h,
+        min_disparity,
+        max_disparity,
+        data: T::into_data(result_diffs),
+        confidence,
+    })
+}
+
+/// Runs the matcher's median and speckle filters over `disparity` with the
+/// kernels.
+pub(crate) fn post_process(matcher: &StereoMatcher, disparity: &mut DisparityMap) -> Result<()> {
+    match disparity.data.disparity_type() {
+        DisparityType::U8 => post_process_as::<u8>(matcher, disparity),
+        DisparityType::U16 => post_process_as::<u16>(matcher, disparity),
+        DisparityType::F32 => post_process_as::<f32>(matcher, disparity),
+    }
+}
+
+fn post_process_as<T: DeviceDisparity>(matcher: &StereoMatcher, disparity: &mut DisparityMap) -> Result<()> {
+    let (result_w, result_h) = (disparity.width, disparity.height);
+    let diff_len = disparity.max_disparity - disparity.min_disparity;
+    let src = kernels::source(&kernel_defines::<T, u8>(matcher, diff_len));
+    let pro_que = build_pro_que(&src, SpatialDims::new(Some(result_w), Some(result_h), Some(1))?)?;
+    let result_diffs = T::data_mut(&mut disparity.data);
+    let result_diffs_buffer = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write().copy_host_ptr())
+        .len(result_diffs.len())
+        .copy_host_slice(&result_diffs[..])
+        .build()?;
+    let result_diffs_buffer = if matcher.median_size > 1 {
+        median_filtered(&pro_que, &result_diffs_buffer, (result_w, result_h))?
+    } else {
+        result_diffs_buffer
+    };
+    if let Some(speckle_filter) = matcher.speckle_filter {
+        remove_speckles(&pro_que, &result_diffs_buffer, (result_w, result_h), speckle_filter)?;
+    }
+    result_diffs_buffer.read(result_diffs).enq()?;
+    Ok(())
+}
+
+/// Results of the `median_filter` kernel, in a new buffer.
+fn median_filtered<T: DeviceDisparity>(
+    pro_que: &ProQue,
+    result_diffs_buffer: &Buffer<T>,
+    (result_w, result_h): (usize, usize),
+) -> Result<Buffer<T>> {
+    let filtered_diffs_buffer: Buffer<T> = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(result_w * result_h)
+        .build()?;
+    let median_kernel = pro_que.kernel_builder("median_filter")
+        .arg(result_diffs_buffer)
+        .arg(&filtered_diffs_buffer)
+        .arg(result_w)
+        .arg(result_h)
+        .global_work_size((result_w, result_h))
+        .build()?;
+
+    unsafe { median_kernel.enq()?; }
+    Ok(filtered_diffs_buffer)
+}
+
+/// Propagates region labels until none changes, then marks the regions of
+/// at most `max_size` results invalid.
+fn remove_speckles<T: DeviceDisparity>(
+    pro_que: &ProQue,
+    result_diffs_buffer: &Buffer<T>,
+    (result_w, result_h): (usize, usize),
+    (max_size, max_difference): (usize, f32),
+) -> Result<()> {
+    let len = result_w * result_h;
+    let labels: Vec<u32> = (0..len as u32).collect();
+    let labels_buffer = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write().copy_host_ptr())
+        .len(len)
+        .copy_host_slice(&labels)
+        .build()?;
+    let changed_buffer: Buffer<u32> = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(1)
+        .build()?;
+    let propagate_kernel = pro_que.kernel_builder("propagate_speckle_labels")
+        .arg(result_diffs_buffer)
+        .arg(&labels_buffer)
+        .arg(&changed_buffer)
+        .arg(result_w)
+        .arg(result_h)
+        .arg(max_difference)
+        .global_work_size((result_w, result_h))
+        .build()?;
+
+    let mut changed = vec![1];
+    while changed[0] != 0 {
+        changed_buffer.write(&[0][..]).enq()?;
+        unsafe { propagate_kernel.enq()?; }
+        changed_buffer.read(&mut changed).enq()?;
+    }
+
+    let sizes_buffer: Buffer<u32> = Buffer::builder()
+        .queue(pro_que.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(len)
+        .fill_val(0)
+        .build()?;
+    let count_kernel = pro_que.kernel_builder("count_speckle_sizes")
+        .arg(result_diffs_buffer)
+        .arg(&labels_buffer)
+        .arg(&sizes_buffer)
+        .arg(result_w)
+        .arg(result_h)
+        .global_work_size((result_w, result_h))
+        .build()?;
+    let remove_kernel = pro_que.kernel_builder("remove_speckles")
+        .arg(result_diffs_buffer)
+        .arg(&labels_buffer)
+        .arg(&sizes_buffer)
+        .arg(result_w)
+        .arg(result_h)
+        .arg(max_size.min(len) as u32)
+        .global_work_size((result_w, result_h))
+        .build()?;
+
+    unsafe {
+        count_kernel.enq()?;
+        remove_kernel.enq()?;
+    }
+    Ok(())
+}
+
+fn max_mem_alloc_size(pro_que: &ProQue) -> Result<u64> {
+    match pro_que.device().info(DeviceInfo::MaxMemAllocSize) {
+        Ok(DeviceInfoResult::MaxMemAllocSize(size)) => Ok(size),
+        Ok(other) => Err(MatchError::Platform(format!("unexpected device info: {:?}", other))),
+        Err(error) => Err(MatchError::Platform(error.to_string())),
+    }
+}
+
+/// Fails when a buffer of `size` bytes holding `what` exceeds the device's
+/// largest allocation, rather than leaving it to a failed enqueue.
+fn check_alloc_size(pro_que: &ProQue, size: u64, what: &str) -> Result<()> {
+    let max_size = max_mem_alloc_size(pro_que)?;
+    if size > max_size {
+        return Err(MatchError::InvalidParameter(format!(
+            "{} take {} bytes but the device allocates at most {}", what, size, max_size
+        )));
+    }
+    Ok(())
+}
+
+/// Builds `src` for the first device of the first platform. Only a failed
+/// program build is a `KernelBuild` error; the rest means no usable platform.
+fn build_pro_que(src: &str, dims: SpatialDims) -> Result<ProQue> {
+    let platform = match ocl::core::get_platform_ids() {
+        Ok(ref platforms) if !platforms.is_empty() => Platform::new(platforms[0]),
+        Ok(_) => return Err(MatchError::Platform("no OpenCL platform found".to_string())),
+        Err(error) => return Err(MatchError::Platform(error.to_string())),
+    };
+    let platform_error = |error: ocl::Error| MatchError::Platform(error.to_string());
+    let device = Device::first(platform).map_err(platform_error)?;
+    let context = Context::builder().platform(platform).devices(device).build().map_err(platform_error)?;
+    let queue = Queue::new(&context, device, None).map_err(platform_error)?;
+    let program = Program::builder()
+        .src(src)
+        .devices(device)
+        .build(&context)
+        .map_err(|error| MatchError::KernelBuild(error.to_string()))?;
+    Ok(ProQue::new(context, queue, program, Some(dims)))
+}
@@ -0,0 +1,187 @@
+use image::{self, DynamicImage, GenericImage, RgbImage};
+use std::fmt;
+use std::str::FromStr;
+
+use error::{MatchError, Result};
+
+/// Gray levels and interleaved RGB of an image file.
+fn get_pixels(file_name: &str) -> Result<(Vec<u8>, Vec<u8>, usize, usize)> {
+    let img = image::open(file_name)
+        .map_err(|error| MatchError::Image { file_name: file_name.to_string(), error })?;
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    Ok((img.grayscale().raw_pixels(), img.to_rgb().into_raw(), width, height))
+}
+
+fn check_len(side: &str, pixels: &[u8], channels: usize, width: usize, height: usize) -> Result<()> {
+    if pixels.len() != width * height * channels {
+        return Err(MatchError::InvalidParameter(format!(
+            "{} image of {}x{} needs {} values but got {}",
+            side, width, height, width * height * channels, pixels.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Crops or pads `pixels` of `channels` values each to `width` by `height`,
+/// repeating the last column or row.
+fn fit(pixels: &[u8], channels: usize, (from_width, from_height): (usize, usize), (width, height): (usize, usize))
+    -> Vec<u8> {
+    let mut fitted = Vec::with_capacity(width * height * channels);
+    for y in 0..height {
+        let row = y.min(from_height - 1) * from_width;
+        for x in 0..width {
+            let i = (row + x.min(from_width - 1)) * channels;
+            fitted.extend_from_slice(&pixels[i..i + channels]);
+        }
+    }
+    fitted
+}
+
+/// What to do when the right image is not the size of the left one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizePolicy {
+    /// Fail with `MatchError::SizeMismatch`.
+    #[default]
+    Strict,
+    /// Crop the right image where it is larger and pad it by repeating its
+    /// last column or row where it is smaller.
+    CropOrPad,
+}
+
+impl SizePolicy {
+    pub fn all() -> [SizePolicy; 2] {
+        [SizePolicy::Strict, SizePolicy::CropOrPad]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SizePolicy::Strict => "strict",
+            SizePolicy::CropOrPad => "crop-or-pad",
+        }
+    }
+}
+
+impl fmt::Display for SizePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SizePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<SizePolicy, String> {
+        SizePolicy::all()
+            .iter()
+            .find(|policy| policy.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown size policy: {}", s))
+    }
+}
+
+/// Grayscale left and right images of a rectified stereo pair, with their
+/// colors when known.
+pub struct StereoPair {
+    pub left: Vec<u8>,
+    pub right: Vec<u8>,
+    /// Interleaved RGB of the left and right images, when known. Only
+    /// `Aggregation::AdaptiveWeights` uses it.
+    pub color: Option<(Vec<u8>, Vec<u8>)>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl StereoPair {
+    /// Fails with `MatchError::SizeMismatch` unless both images have the same size.
+    pub fn open(left_file_name: &str, right_file_name: &str) -> Result<StereoPair> {
+        StereoPair::open_with(left_file_name, right_file_name, SizePolicy::Strict)
+    }
+
+    pub fn open_with(left_file_name: &str, right_file_name: &str, policy: SizePolicy) -> Result<StereoPair> {
+        let (left, left_color, width, height) = get_pixels(left_file_name)?;
+        let (right, right_color, right_width, right_height) = get_pixels(right_file_name)?;
+        StereoPair::from_images(
+            (left, right),
+            Some((left_color, right_color)),
+            (width, height),
+            (right_width, right_height),
+            policy,
+        )
+    }
+
+    pub fn from_gray_pixels(left: Vec<u8>, right: Vec<u8>, width: usize, height: usize) -> Result<StereoPair> {
+        StereoPair::from_gray_images(left, (width, height), right, (width, height), SizePolicy::Strict)
+    }
+
+    /// Makes a pair of images that may differ in size, resolved by `policy`.
+    /// The result always has the size of the left image.
+    pub fn from_gray_images(
+        left: Vec<u8>,
+        (width, height): (usize, usize),
+        right: Vec<u8>,
+        (right_width, right_height): (usize, usize),
+        policy: SizePolicy,
+    ) -> Result<StereoPair> {
+        StereoPair::from_images((left, right), None, (width, height), (right_width, right_height), policy)
+    }
+
+    /// Makes a pair from interleaved RGB, converted to gray as image files are.
+    pub fn from_rgb_pixels(left: Vec<u8>, right: Vec<u8>, width: usize, height: usize) -> Result<StereoPair> {
+        check_len("left", &left, 3, width, height)?;
+        check_len("right", &right, 3, width, height)?;
+        let gray = |rgb: &[u8]| {
+            let img = RgbImage::from_raw(width as u32, height as u32, rgb.to_vec()).unwrap();
+            DynamicImage::ImageRgb8(img).grayscale().raw_pixels()
+        };
+        let gray_pixels = (gray(&left), gray(&right));
+        StereoPair::from_images(gray_pixels, Some((left, right)), (width, height), (width, height), SizePolicy::Strict)
+    }
+
+    fn from_images(
+        (left, right): (Vec<u8>, Vec<u8>),
+        color: Option<(Vec<u8>, Vec<u8>)>,
+        (width, height): (usize, usize),
+        (right_width, right_height): (usize, usize),
+        policy: SizePolicy,
+    ) -> Result<StereoPair> {
+        check_len("left", &left, 1, width, height)?;
+        check_len("right", &right, 1, right_width, right_height)?;
+        if let Some((ref left_color, ref right_color)) = color {
+            check_len("left", left_color, 3, width, height)?;
+            check_len("right", right_color, 3, right_width, right_height)?;
+        }
+        if (right_width, right_height) == (width, height) {
+            return Ok(StereoPair { left, right, color, width, height });
+        }
+        if policy == SizePolicy::Strict || right_width == 0 || right_height == 0 {
+            return Err(MatchError::SizeMismatch {
+                left: (width, height),
+                right: (right_width, right_height),
+            });
+        }
+
+        let from = (right_width, right_height);
+        let fitted = fit(&right, 1, from, (width, height));
+        let color = color.map(|(left_color, right_color)| (left_color, fit(&right_color, 3, from, (width, height))));
+        Ok(StereoPair { left, right: fitted, color, width, height })
+    }
+
+    /// The pair as seen in a mirror: both images flipped horizontally, the
+    /// left one becoming the right. Its disparities are those of the left
+    /// image, flipped.
+    pub(crate) fn mirrored(&self) -> StereoPair {
+        let flip = |pixels: &[u8], channels: usize| -> Vec<u8> {
+            pixels
+                .chunks(self.width * channels)
+                .flat_map(|row| row.chunks(channels).rev().flat_map(|pixel| pixel.iter().cloned()))
+                .collect()
+        };
+        StereoPair {
+            left: flip(&self.right, 1),
+            right: flip(&self.left, 1),
+            color: self.color.as_ref().map(|(left, right)| (flip(right, 3), flip(left, 3))),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
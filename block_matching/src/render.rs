@@ -0,0 +1,88 @@
+use std::fmt;
+use std::str::FromStr;
+
+fn hsv_to_rgb(h: u8, s: u8, v: u8) -> Vec<u8> {
+    let hf = (h as f32 * 360. / u8::MAX as f32) / 60.;
+    let sf = s as f32 / u8::MAX as f32;
+    let vf = v as f32;
+    let h_floor = hf.floor();
+    let ff = hf - h_floor;
+    let p = (vf * (1. - sf)) as u8;
+    let q = (vf * (1. - sf * ff)) as u8;
+    let t = (vf * (1. - sf * (1. - ff))) as u8;
+
+    match h_floor as u8 {
+        0 => vec![v, t, p],
+        1 => vec![q, v, p],
+        2 => vec![p, v, t],
+        3 => vec![p, q, v],
+        4 => vec![t, p, v],
+        5 => vec![v, p, q],
+        6 => vec![v, t, p],
+        _ => vec![0, 0, 0],
+    }
+}
+
+/// How a disparity is turned into a pixel color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMap {
+    /// Red for the largest disparity through blue for the smallest.
+    #[default]
+    Hue,
+    /// White for the largest disparity through black for the smallest.
+    Gray,
+}
+
+impl ColorMap {
+    pub fn all() -> [ColorMap; 2] {
+        [ColorMap::Hue, ColorMap::Gray]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ColorMap::Hue => "hue",
+            ColorMap::Gray => "gray",
+        }
+    }
+
+    /// Color of invalid disparities, which no disparity maps to: black for
+    /// `Hue` and red for `Gray`.
+    pub fn invalid_color(&self) -> [u8; 3] {
+        match *self {
+            ColorMap::Hue => [0, 0, 0],
+            ColorMap::Gray => [255, 0, 0],
+        }
+    }
+
+    /// `ratio` is 0 for the smallest disparity and 1 for the largest.
+    pub fn color(&self, ratio: f32) -> Vec<u8> {
+        match *self {
+            ColorMap::Hue => {
+                let h = (1.0 - ratio) * 200.0;
+                hsv_to_rgb(h as u8, 255, 255)
+            }
+            ColorMap::Gray => {
+                let v = (ratio * 255.0) as u8;
+                vec![v, v, v]
+            }
+        }
+    }
+}
+
+impl fmt::Display for ColorMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ColorMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorMap, String> {
+        ColorMap::all()
+            .iter()
+            .find(|colormap| colormap.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown colormap: {}", s))
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Steps (dx, dy) of the 16 SGM path directions: horizontal and vertical,
+/// then diagonal, then knight moves.
+const DIRECTIONS: [(i32, i32); 16] = [
+    (1, 0), (-1, 0), (0, 1), (0, -1),
+    (1, 1), (-1, -1), (1, -1), (-1, 1),
+    (2, 1), (-2, -1), (2, -1), (-2, 1), (1, 2), (-1, -2), (1, -2), (-1, 2),
+];
+
+/// Directions `Aggregation::Sgm` accumulates costs along. More paths smooth
+/// better and cost proportionally more time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SgmPaths {
+    /// Horizontal and vertical.
+    Four,
+    /// Also diagonal.
+    #[default]
+    Eight,
+    /// Also the knight moves between them, such as two right and one down.
+    Sixteen,
+}
+
+impl SgmPaths {
+    pub fn all() -> [SgmPaths; 3] {
+        [SgmPaths::Four, SgmPaths::Eight, SgmPaths::Sixteen]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SgmPaths::Four => "4",
+            SgmPaths::Eight => "8",
+            SgmPaths::Sixteen => "16",
+        }
+    }
+
+    pub(crate) fn directions(&self) -> &'static [(i32, i32)] {
+        match *self {
+            SgmPaths::Four => &DIRECTIONS[..4],
+            SgmPaths::Eight => &DIRECTIONS[..8],
+            SgmPaths::Sixteen => &DIRECTIONS[..],
+        }
+    }
+}
+
+impl fmt::Display for SgmPaths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SgmPaths {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SgmPaths, String> {
+        SgmPaths::all()
+            .iter()
+            .find(|paths| paths.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown SGM paths: {}", s))
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How the disparity of the smallest cost is refined from the costs of the
+/// disparities on either side of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubpixelFit {
+    /// Integer disparities.
+    #[default]
+    None,
+    /// Vertex of the parabola through the three costs, suited to squared
+    /// differences.
+    Parabola,
+    /// Vertex of the V through the three costs with equal slopes on both
+    /// sides, suited to absolute differences.
+    Equiangular,
+}
+
+impl SubpixelFit {
+    pub fn all() -> [SubpixelFit; 3] {
+        [SubpixelFit::None, SubpixelFit::Parabola, SubpixelFit::Equiangular]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SubpixelFit::None => "none",
+            SubpixelFit::Parabola => "parabola",
+            SubpixelFit::Equiangular => "equiangular",
+        }
+    }
+
+    /// Value of `SUBPIXEL_FIT` in the kernel source.
+    #[cfg(feature = "opencl")]
+    pub(crate) fn kernel_value(&self) -> usize {
+        match *self {
+            SubpixelFit::None => 0,
+            SubpixelFit::Parabola => 1,
+            SubpixelFit::Equiangular => 2,
+        }
+    }
+
+    /// Offset from the disparity of the smallest cost `center` toward the
+    /// vertex, within half a pixel; `lower` and `upper` are the costs one
+    /// disparity below and above. Mirrors `refine_disparity` in the kernels.
+    pub(crate) fn offset(&self, lower: f32, center: f32, upper: f32) -> f32 {
+        match *self {
+            SubpixelFit::None => 0.0,
+            SubpixelFit::Parabola => {
+                let curvature = lower - 2.0 * center + upper;
+                if curvature > 0.0 { (lower - upper) / (2.0 * curvature) } else { 0.0 }
+            }
+            SubpixelFit::Equiangular => {
+                let slope = (lower - center).max(upper - center);
+                if slope > 0.0 { (lower - upper) / (2.0 * slope) } else { 0.0 }
+            }
+        }
+    }
+}
+
+impl fmt::Display for SubpixelFit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SubpixelFit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SubpixelFit, String> {
+        SubpixelFit::all()
+            .iter()
+            .find(|fit| fit.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown subpixel fit: {}", s))
+    }
+}
@@ -0,0 +1,167 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{
+    Aggregation, BorderPolicy, CostFunction, CostVolume, Density, DisparityMap, MatchError, SgmPaths, StereoMatcher,
+    StereoPair,
+};
+use common::Rect;
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 16;
+const SHIFT: usize = 3;
+const FOREGROUND_SHIFT: usize = 7;
+
+const FLAT: Rect = (8, 16, 5, 11);
+const FOREGROUND: Rect = (6, 14, 4, 12);
+
+fn is_flat(x: usize, y: usize) -> bool {
+    common::contains(FLAT, x, y)
+}
+
+fn pair_with_flat_patch() -> StereoPair {
+    common::pair_with_flat_patch((WIDTH, HEIGHT), FLAT, SHIFT)
+}
+
+#[test]
+fn sgm_fills_flat_patch() {
+    let pair = pair_with_flat_patch();
+    let matcher = StereoMatcher::new().block_size(3, 3).max_disparity(6);
+    let boxed = matcher.clone().compute(&pair).unwrap();
+    assert!((0..WIDTH * HEIGHT).any(|i| {
+        is_flat(i % WIDTH, i / WIDTH) && boxed.get(i % WIDTH, i / WIDTH) != Some(SHIFT as f32)
+    }));
+    for &sgm_paths in SgmPaths::all().iter() {
+        let disparity = matcher.clone().aggregation(Aggregation::Sgm).sgm_paths(sgm_paths).compute(&pair).unwrap();
+        assert_eq!((disparity.width, disparity.height), (WIDTH, HEIGHT));
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH - SHIFT {
+                assert_eq!(disparity.get(x, y), Some(SHIFT as f32), "paths {}, ({}, {})", sgm_paths, x, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn sgm_rejects_unsupported_settings() {
+    let pair = pair_with_flat_patch();
+    let sgm = StereoMatcher::new().block_size(3, 3).max_disparity(6).aggregation(Aggregation::Sgm);
+    for matcher in [
+        sgm.clone().density(Density::Tiled),
+        sgm.clone().cost_function(CostFunction::Zncc),
+        sgm.clone().cost_volume(CostVolume::Streamed),
+        sgm.clone().sgm_penalties(40, 3),
+        sgm.clone().cost_function(CostFunction::Ssd).sgm_penalties(1, u32::MAX / 8),
+    ].iter() {
+        match matcher.compute(&pair) {
+            Err(MatchError::InvalidParameter(_)) => {}
+            other => panic!("{:?} gave {:?}", matcher, other.map(|disparity| disparity.width)),
+        }
+    }
+}
+
+fn is_foreground(x: usize, y: usize) -> bool {
+    common::contains(FOREGROUND, x, y)
+}
+
+fn color_pair_with_foreground() -> StereoPair {
+    common::color_pair_with_foreground((WIDTH, HEIGHT), FOREGROUND, (SHIFT, FOREGROUND_SHIFT))
+}
+
+// Pixels whose match is not hidden by the square and that have the wrong
+// disparity.
+fn wrong_disparities(disparity: &DisparityMap) -> usize {
+    let mut wrong = 0;
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH - FOREGROUND_SHIFT {
+            let expected = if is_foreground(x, y) { FOREGROUND_SHIFT } else { SHIFT };
+            let occluded = (0..WIDTH).any(|foreground_x| {
+                is_foreground(foreground_x, y) && foreground_x + FOREGROUND_SHIFT == x + expected
+            });
+            if !is_foreground(x, y) && occluded {
+                continue;
+            }
+            if disparity.get(x, y) != Some(expected as f32) {
+                wrong += 1;
+            }
+        }
+    }
+    wrong
+}
+
+#[test]
+fn adaptive_weights_keep_edges() {
+    let pair = color_pair_with_foreground();
+    let matcher = StereoMatcher::new().block_size(7, 7).max_disparity(10);
+    let boxed = matcher.clone().compute(&pair).unwrap();
+    assert!(wrong_disparities(&boxed) > 0);
+    let adaptive = matcher.aggregation(Aggregation::AdaptiveWeights).compute(&pair).unwrap();
+    assert_eq!(wrong_disparities(&adaptive), 0);
+}
+
+#[test]
+fn cross_regions_keep_edges() {
+    let pair = color_pair_with_foreground();
+    let disparity = StereoMatcher::new()
+        .max_disparity(10)
+        .aggregation(Aggregation::Cross)
+        .cross_arms(40, 4)
+        .compute(&pair)
+        .unwrap();
+    assert_eq!(wrong_disparities(&disparity), 0);
+}
+
+// The gray guide tells the square from the background less well than color.
+#[test]
+fn guided_filter_blurs_edges_less_than_box() {
+    let pair = color_pair_with_foreground();
+    let matcher = StereoMatcher::new().block_size(7, 7).max_disparity(10);
+    let boxed = matcher.clone().compute(&pair).unwrap();
+    let guided = matcher.aggregation(Aggregation::Guided).compute(&pair).unwrap();
+    assert!(wrong_disparities(&guided) < wrong_disparities(&boxed));
+}
+
+#[test]
+fn integral_images_match_box() {
+    let pair = pair_with_flat_patch();
+    for &(block_w, block_h) in [(1, 1), (3, 3), (4, 7), (15, 15), (WIDTH, HEIGHT)].iter() {
+        for &density in Density::all().iter() {
+            for &border in BorderPolicy::all().iter() {
+                let matcher = StereoMatcher::new()
+                    .block_size(block_w, block_h)
+                    .min_disparity(1)
+                    .max_disparity(6)
+                    .density(density)
+                    .border(border);
+                let boxed = matcher.clone().compute(&pair).unwrap();
+                let integral = matcher.aggregation(Aggregation::IntegralBox).compute(&pair).unwrap();
+                assert!(
+                    integral.data == boxed.data,
+                    "{}x{} block, density {}, border {}", block_w, block_h, density, border
+                );
+            }
+        }
+    }
+    for &cost_function in CostFunction::all().iter().filter(|&&c| c != CostFunction::Zncc) {
+        let matcher = StereoMatcher::new().block_size(9, 9).max_disparity(6).cost_function(cost_function);
+        let boxed = matcher.clone().compute(&pair).unwrap();
+        let integral = matcher.aggregation(Aggregation::IntegralBox).compute(&pair).unwrap();
+        assert!(integral.data == boxed.data, "cost {}", cost_function);
+    }
+}
+
+#[test]
+fn integral_images_reject_unsupported_settings() {
+    let pair = pair_with_flat_patch();
+    let integral = StereoMatcher::new().block_size(3, 3).max_disparity(6).aggregation(Aggregation::IntegralBox);
+    for matcher in [
+        integral.clone().cost_function(CostFunction::Zncc),
+        integral.clone().cost_volume(CostVolume::Streamed),
+    ].iter() {
+        match matcher.compute(&pair) {
+            Err(MatchError::InvalidParameter(_)) => {}
+            other => panic!("{:?} gave {:?}", matcher, other.map(|disparity| disparity.width)),
+        }
+    }
+}
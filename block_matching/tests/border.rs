@@ -0,0 +1,64 @@
+extern crate block_matching;
+
+use block_matching::{BorderPolicy, DispatchStrategy, StereoMatcher, StereoPair};
+
+const WIDTH: usize = 8;
+const HEIGHT: usize = 4;
+
+fn row_value(y: usize) -> u8 {
+    (y * 40) as u8
+}
+
+// Every row has one brightness, except that the last right pixel of a row
+// equals the next left row. Reading past the right edge into the next row
+// would match it perfectly at disparity 1.
+fn wrapping_pair() -> StereoPair {
+    let left: Vec<u8> = (0..WIDTH * HEIGHT).map(|i| row_value(i / WIDTH)).collect();
+    let mut right = left.clone();
+    for y in 0..HEIGHT - 1 {
+        right[y * WIDTH + WIDTH - 1] = row_value(y + 1);
+    }
+    StereoPair::from_gray_pixels(left, right, WIDTH, HEIGHT).unwrap()
+}
+
+#[test]
+fn last_column_does_not_match_next_row() {
+    let pair = wrapping_pair();
+    for &dispatch in DispatchStrategy::all().iter() {
+        for &border in BorderPolicy::all().iter() {
+            let disparity = StereoMatcher::new()
+                .block_size(1, 1)
+                .max_disparity(2)
+                .dispatch(dispatch)
+                .border(border)
+                .border_value(255)
+                .compute(&pair)
+                .unwrap();
+            for y in 0..HEIGHT - 1 {
+                assert_eq!(
+                    disparity.get(WIDTH - 1, y), Some(0.0),
+                    "dispatch {}, border {}, row {}", dispatch, border, y
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn invalid_border_marks_blocks_without_disparity() {
+    let pair = wrapping_pair();
+    for &dispatch in DispatchStrategy::all().iter() {
+        let disparity = StereoMatcher::new()
+            .block_size(1, 1)
+            .min_disparity(1)
+            .max_disparity(3)
+            .dispatch(dispatch)
+            .border(BorderPolicy::Invalid)
+            .compute(&pair)
+            .unwrap();
+        for y in 0..HEIGHT {
+            assert_eq!(disparity.get(WIDTH - 1, y), None, "dispatch {}, row {}", dispatch, y);
+            assert!((0..WIDTH - 1).all(|x| disparity.get(x, y).is_some()));
+        }
+    }
+}
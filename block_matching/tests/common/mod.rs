@@ -0,0 +1,119 @@
+// Fixtures shared by the integration tests; each test file uses only some.
+#![allow(dead_code)]
+
+use std::io::{self, Write};
+
+use block_matching::{Backend, DisparityMap, MatchError, StereoMatcher, StereoPair};
+
+/// Pixels `[x_begin, x_end) x [y_begin, y_end)` as
+/// `(x_begin, x_end, y_begin, y_end)`.
+pub type Rect = (usize, usize, usize, usize);
+
+pub fn contains((x_begin, x_end, y_begin, y_end): Rect, x: usize, y: usize) -> bool {
+    (x_begin..x_end).contains(&x) && (y_begin..y_end).contains(&y)
+}
+
+/// Gray levels that repeat rarely enough for any block to match at one
+/// disparity only.
+pub fn texture(x: usize, y: usize) -> u8 {
+    ((x * 37 + y * 101 + x * y * 13) % 251) as u8
+}
+
+/// Pixel `(x, y)` of a `width` x `height` image at index `y * width + x`.
+pub fn pixels<P, F>((width, height): (usize, usize), pixel: F) -> Vec<P>
+where
+    F: Fn(usize, usize) -> P,
+{
+    (0..width * height).map(|i| pixel(i % width, i / width)).collect()
+}
+
+pub fn pair_from<F, G>(size: (usize, usize), left: F, right: G) -> StereoPair
+where
+    F: Fn(usize, usize) -> u8,
+    G: Fn(usize, usize) -> u8,
+{
+    StereoPair::from_gray_pixels(pixels(size, left), pixels(size, right), size.0, size.1).unwrap()
+}
+
+pub fn color_pair_from<F, G>(size: (usize, usize), left: F, right: G) -> StereoPair
+where
+    F: Fn(usize, usize) -> [u8; 3],
+    G: Fn(usize, usize) -> [u8; 3],
+{
+    StereoPair::from_rgb_pixels(pixels(size, left).concat(), pixels(size, right).concat(), size.0, size.1).unwrap()
+}
+
+/// Left is the textured right image moved `shift` pixels to the right, so
+/// every right pixel matches at disparity `shift`.
+pub fn shifted_pair(size: (usize, usize), shift: usize) -> StereoPair {
+    pair_from(size, |x, y| texture(x.saturating_sub(shift), y), texture)
+}
+
+/// Disparities 2 to 4 in turn down the rows.
+pub fn textured_pair(size: (usize, usize)) -> StereoPair {
+    pair_from(size, |x, y| texture(x.saturating_sub(2 + y % 3), y), texture)
+}
+
+/// `shifted_pair` with a patch of uniform gray that matches equally well at
+/// any disparity.
+pub fn pair_with_flat_patch(size: (usize, usize), flat: Rect, shift: usize) -> StereoPair {
+    let patched = |x, y| if contains(flat, x, y) { 128 } else { texture(x, y) };
+    pair_from(size, |x, y| patched(x.saturating_sub(shift), y), patched)
+}
+
+/// Whether the 3x3 block centered on `(x, y)` lies in the `flat` patch.
+pub fn is_flat_block(flat: Rect, x: usize, y: usize) -> bool {
+    (x - 1..x + 2).all(|block_x| (y - 1..y + 2).all(|block_y| contains(flat, block_x, block_y)))
+}
+
+/// A textured square at disparity `foreground_shift` in front of a textured
+/// background at disparity `shift`.
+pub fn pair_with_foreground(size: (usize, usize), foreground: Rect, shifts: (usize, usize)) -> StereoPair {
+    let square = |x: usize, y: usize| texture(y + 50, x + 11);
+    let (left, right) = layered(size, foreground, shifts, texture, square);
+    StereoPair::from_gray_pixels(left, right, size.0, size.1).unwrap()
+}
+
+/// `pair_with_foreground` in RGB, with a red square on a blue background.
+pub fn color_pair_with_foreground(size: (usize, usize), foreground: Rect, shifts: (usize, usize)) -> StereoPair {
+    let background = |x: usize, y: usize| [texture(x, y) / 5, texture(x, y) / 10, 150 + texture(x, y) / 5];
+    let square = |x: usize, y: usize| [150 + texture(x, y) / 5, texture(y, x) / 10, texture(y, x) / 5];
+    let (left, right) = layered(size, foreground, shifts, background, square);
+    StereoPair::from_rgb_pixels(left.concat(), right.concat(), size.0, size.1).unwrap()
+}
+
+/// Left and right pixels of `square` over the `foreground` rectangle at
+/// disparity `foreground_shift`, in front of `background` at disparity
+/// `shift`.
+fn layered<P, B, S>(
+    size: (usize, usize),
+    foreground: Rect,
+    (shift, foreground_shift): (usize, usize),
+    background: B,
+    square: S,
+) -> (Vec<P>, Vec<P>)
+where
+    B: Fn(usize, usize) -> P,
+    S: Fn(usize, usize) -> P,
+{
+    let left = pixels(size, |x, y| match x.checked_sub(foreground_shift) {
+        Some(foreground_x) if contains(foreground, foreground_x, y) => square(foreground_x, y),
+        _ => background(x.saturating_sub(shift), y),
+    });
+    let right = pixels(size, |x, y| if contains(foreground, x, y) { square(x, y) } else { background(x, y) });
+    (left, right)
+}
+
+/// Matches on the OpenCL backend, or reports the skip and gives `None` when
+/// there is no OpenCL platform.
+pub fn compute_on_opencl(matcher: StereoMatcher, pair: &StereoPair, context: &str) -> Option<DisparityMap> {
+    match matcher.backend(Backend::OpenCl).compute(pair) {
+        Err(MatchError::Platform(message)) => {
+            // Straight to stderr, which the test harness does not capture.
+            let report = format!("skipped {}: no OpenCL platform: {}\n", context, message);
+            io::stderr().write_all(report.as_bytes()).unwrap();
+            None
+        }
+        result => Some(result.unwrap()),
+    }
+}
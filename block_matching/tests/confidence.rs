@@ -0,0 +1,135 @@
+extern crate block_matching;
+extern crate image;
+
+mod common;
+
+use block_matching::{ConfidenceMap, ConfidenceMeasure, CostFunction, Density, MatchError, StereoMatcher, StereoPair};
+use common::Rect;
+use std::env;
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 16;
+const SHIFT: usize = 3;
+
+const FLAT: Rect = (8, 16, 4, 12);
+
+fn is_flat(x: usize, y: usize) -> bool {
+    common::contains(FLAT, x, y)
+}
+
+fn pair_with_flat_patch() -> StereoPair {
+    common::pair_with_flat_patch((WIDTH, HEIGHT), FLAT, SHIFT)
+}
+
+fn matcher() -> StereoMatcher {
+    StereoMatcher::new().block_size(3, 3).max_disparity(8)
+}
+
+#[test]
+fn cost_confidence_separates_unique_matches() {
+    let pair = pair_with_flat_patch();
+    assert!(matcher().compute(&pair).unwrap().confidence.is_none());
+    let ratio = matcher().confidence(ConfidenceMeasure::Ratio).compute(&pair).unwrap().confidence.unwrap();
+    let curvature = matcher().confidence(ConfidenceMeasure::Curvature).compute(&pair).unwrap().confidence.unwrap();
+    let difference = matcher().confidence(ConfidenceMeasure::Difference).compute(&pair).unwrap().confidence.unwrap();
+    assert_eq!((ratio.measure, curvature.measure), (ConfidenceMeasure::Ratio, ConfidenceMeasure::Curvature));
+    assert_eq!(difference.measure, ConfidenceMeasure::Difference);
+    assert_eq!((ratio.width, ratio.height), (WIDTH, HEIGHT));
+    for y in 1..HEIGHT - 1 {
+        for x in 1..WIDTH - SHIFT - 1 {
+            // Exact matches cost nothing, and flat blocks as much at several
+            // disparities in a row.
+            if common::is_flat_block(FLAT, x, y) {
+                assert_eq!(ratio.get(x, y), 0.0, "({}, {})", x, y);
+                assert!(curvature.get(x, y) <= 0.5, "({}, {}) {}", x, y, curvature.get(x, y));
+                assert_eq!(difference.get(x, y), 0.0, "({}, {})", x, y);
+            } else if !is_flat(x, y) {
+                assert_eq!(ratio.get(x, y), 1.0, "({}, {})", x, y);
+                assert!(curvature.get(x, y) > 0.5, "({}, {}) {}", x, y, curvature.get(x, y));
+                assert!(difference.get(x, y) > 0.0, "({}, {}) {}", x, y, difference.get(x, y));
+            }
+        }
+    }
+}
+
+#[test]
+fn difference_confidence_supports_zncc() {
+    let confidence = matcher()
+        .cost_function(CostFunction::Zncc)
+        .confidence(ConfidenceMeasure::Difference)
+        .compute(&pair_with_flat_patch())
+        .unwrap()
+        .confidence
+        .unwrap();
+    for y in 1..HEIGHT - 1 {
+        for x in 1..WIDTH - SHIFT - 1 {
+            let value = confidence.get(x, y);
+            assert!((0.0..=1.0).contains(&value), "({}, {}) {}", x, y, value);
+            // Blocks clear of the patch correlate fully at SHIFT only.
+            if (x - 1..x + 2).all(|block_x| (y - 1..y + 2).all(|block_y| !is_flat(block_x, block_y))) {
+                assert!(value > 0.0, "({}, {}) {}", x, y, value);
+            }
+        }
+    }
+}
+
+#[test]
+fn left_right_confidence_is_zero_where_invalid() {
+    let pair = pair_with_flat_patch();
+    let disparity = matcher()
+        .uniqueness_ratio(10)
+        .confidence(ConfidenceMeasure::LeftRight)
+        .compute(&pair)
+        .unwrap();
+    let confidence = disparity.confidence.as_ref().unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let value = confidence.get(x, y);
+            assert!((0.0..=1.0).contains(&value), "({}, {}) {}", x, y, value);
+            if disparity.get(x, y).is_none() {
+                assert_eq!(value, 0.0, "({}, {})", x, y);
+            }
+        }
+    }
+    assert_eq!(confidence.get(WIDTH / 2, 1), 1.0);
+    let checked = matcher().left_right_check(0.0).confidence(ConfidenceMeasure::Ratio).compute(&pair).unwrap();
+    let checked_confidence = checked.confidence.as_ref().unwrap();
+    assert!((0..WIDTH * HEIGHT).all(|i| checked.data.get(i).is_some() || checked_confidence.data[i] == 0.0));
+}
+
+#[test]
+fn confidence_saves_as_gray_image() {
+    let disparity = matcher().confidence(ConfidenceMeasure::Ratio).compute(&pair_with_flat_patch()).unwrap();
+    let confidence = disparity.confidence.unwrap();
+    let file_name = env::temp_dir().join("block_matching_confidence.png");
+    let file_name = file_name.to_str().unwrap();
+    confidence.save(file_name).unwrap();
+    let image = image::open(file_name).unwrap().to_luma();
+    assert_eq!(image.dimensions(), (WIDTH as u32, HEIGHT as u32));
+    assert_eq!(image.get_pixel(12, 8).data, [0]);
+    assert_eq!(image.get_pixel(2, 1).data, [255]);
+}
+
+#[test]
+fn gray_image_needs_a_full_map() {
+    let confidence = ConfidenceMap { width: 4, height: 2, measure: ConfidenceMeasure::Ratio, data: vec![1.0; 7] };
+    match confidence.to_gray_image() {
+        Err(MatchError::InvalidParameter(_)) => {}
+        other => panic!("7 confidences for 4x2 gave {:?}", other.map(|image| image.dimensions())),
+    }
+}
+
+#[test]
+fn confidence_rejects_unsupported_settings() {
+    let pair = pair_with_flat_patch();
+    for matcher in [
+        matcher().cost_function(CostFunction::Zncc).confidence(ConfidenceMeasure::Ratio),
+        matcher().cost_function(CostFunction::Zncc).confidence(ConfidenceMeasure::Curvature),
+        matcher().density(Density::Tiled).confidence(ConfidenceMeasure::LeftRight),
+    ].iter() {
+        match matcher.compute(&pair) {
+            Err(MatchError::InvalidParameter(_)) => {}
+            other => panic!("{:?} gave {:?}", matcher, other.map(|disparity| disparity.width)),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{ColorMap, Density, MatchError, StereoMatcher, StereoPair};
+use common::Rect;
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 16;
+const SHIFT: usize = 2;
+const FOREGROUND_SHIFT: usize = 6;
+
+const FOREGROUND: Rect = (10, 18, 4, 12);
+
+fn is_foreground(x: usize, y: usize) -> bool {
+    common::contains(FOREGROUND, x, y)
+}
+
+fn pair_with_foreground() -> StereoPair {
+    common::pair_with_foreground((WIDTH, HEIGHT), FOREGROUND, (SHIFT, FOREGROUND_SHIFT))
+}
+
+// Background pixels of the right image whose match the square hides in the
+// left image.
+fn is_occluded(x: usize, y: usize) -> bool {
+    !is_foreground(x, y) && (x + SHIFT).checked_sub(FOREGROUND_SHIFT).is_some_and(|x| is_foreground(x, y))
+}
+
+#[test]
+fn left_right_check_removes_occlusions() {
+    let pair = pair_with_foreground();
+    let matcher = StereoMatcher::new().block_size(1, 1).max_disparity(8);
+    let unchecked = matcher.clone().compute(&pair).unwrap();
+    let checked = matcher.left_right_check(0.0).compute(&pair).unwrap();
+    // Left of SHIFT, the padded left image matches at disparity 0 too.
+    let xs = SHIFT..WIDTH - FOREGROUND_SHIFT;
+    let mut kept = 0;
+    for y in 0..HEIGHT {
+        for x in xs.clone() {
+            let expected = if is_foreground(x, y) { FOREGROUND_SHIFT } else { SHIFT };
+            if is_occluded(x, y) {
+                assert!(unchecked.get(x, y).is_some(), "({}, {})", x, y);
+                assert_eq!(checked.get(x, y), None, "({}, {})", x, y);
+            } else if let Some(disparity) = checked.get(x, y) {
+                assert_eq!(disparity, expected as f32, "({}, {})", x, y);
+                kept += 1;
+            }
+        }
+    }
+    assert!(kept > xs.len() * HEIGHT * 3 / 4, "kept {}", kept);
+}
+
+#[test]
+fn invalid_disparities_render_apart() {
+    let pair = pair_with_foreground();
+    let disparity = StereoMatcher::new().block_size(1, 1).max_disparity(8).left_right_check(0.0).compute(&pair).unwrap();
+    let (x, y) = (0..WIDTH * HEIGHT)
+        .map(|i| (i % WIDTH, i / WIDTH))
+        .find(|&(x, y)| is_occluded(x, y))
+        .unwrap();
+    for &colormap in ColorMap::all().iter() {
+        let image = disparity.to_rgb_image(colormap).unwrap();
+        let invalid = image.get_pixel(x as u32, y as u32).data;
+        assert_eq!(invalid, colormap.invalid_color(), "colormap {}", colormap);
+        assert!((0..=100).all(|i| colormap.color(i as f32 / 100.0) != invalid.to_vec()), "colormap {}", colormap);
+    }
+}
+
+#[test]
+fn left_right_check_rejects_unsupported_settings() {
+    let pair = pair_with_foreground();
+    for matcher in [
+        StereoMatcher::new().left_right_check(-1.0),
+        StereoMatcher::new().left_right_check(1.0).density(Density::Tiled),
+    ].iter() {
+        match matcher.compute(&pair) {
+            Err(MatchError::InvalidParameter(_)) => {}
+            other => panic!("{:?} gave {:?}", matcher, other.map(|disparity| disparity.width)),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{BorderPolicy, CostFunction, Density, StereoMatcher, StereoPair};
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 12;
+const SHIFT: usize = 3;
+
+// Dark enough to brighten without saturating.
+fn dim_texture(x: usize, y: usize) -> u8 {
+    common::texture(x, y) / 3
+}
+
+#[test]
+fn zncc_ignores_exposure_difference() {
+    // The left camera is brighter and has more gain.
+    let pair = common::pair_from((WIDTH, HEIGHT), |x, y| dim_texture(x.saturating_sub(SHIFT), y) * 2 + 50, dim_texture);
+    let disparity = StereoMatcher::new()
+        .block_size(5, 5)
+        .max_disparity(6)
+        .cost_function(CostFunction::Zncc)
+        .compute(&pair)
+        .unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH - SHIFT - 2 {
+            assert_eq!(disparity.get(x, y), Some(SHIFT as f32), "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn census_and_rank_ignore_exposure_offset() {
+    let pair = common::pair_from((WIDTH, HEIGHT), |x, y| dim_texture(x.saturating_sub(SHIFT), y) + 60, dim_texture);
+    // Census bits fit in a u32 for 5x5 and need a u64 for 7x9.
+    for &cost_function in [CostFunction::Census, CostFunction::Rank].iter() {
+        for &(window_w, window_h) in [(5, 5), (7, 9)].iter() {
+            let disparity = StereoMatcher::new()
+                .block_size(5, 5)
+                .max_disparity(6)
+                .cost_function(cost_function)
+                .transform_window(window_w, window_h)
+                .compute(&pair)
+                .unwrap();
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH - SHIFT - 2 {
+                    assert_eq!(
+                        disparity.get(x, y), Some(SHIFT as f32),
+                        "{} {}x{}, ({}, {})", cost_function, window_w, window_h, x, y
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn birchfield_tomasi_ignores_half_pixel_shift() {
+    // A ramp sampled half a pixel apart matches exactly with interpolation but
+    // not at integer positions.
+    let right: Vec<u8> = (0..WIDTH * HEIGHT).map(|i| (i % WIDTH * 10 + 5) as u8).collect();
+    let left: Vec<u8> = (0..WIDTH * HEIGHT).map(|i| ((i % WIDTH).saturating_sub(SHIFT) * 10) as u8).collect();
+    let pair = StereoPair::from_gray_pixels(left, right, WIDTH, HEIGHT).unwrap();
+    for &density in Density::all().iter() {
+        let disparity = StereoMatcher::new()
+            .block_size(3, 3)
+            .max_disparity(6)
+            .density(density)
+            .cost_function(CostFunction::BirchfieldTomasi)
+            .border(BorderPolicy::Invalid)
+            .compute(&pair)
+            .unwrap();
+        for y in 0..disparity.height {
+            for x in 0..disparity.width {
+                // Only blocks that can still shift by SHIFT + 1 inside the left image.
+                let block_right = if density == Density::Dense { x + 2 } else { (x + 1) * 3 };
+                if block_right + SHIFT + 1 > WIDTH {
+                    continue;
+                }
+                let d = disparity.get(x, y);
+                assert!(
+                    d == Some(SHIFT as f32) || d == Some((SHIFT + 1) as f32),
+                    "{} ({}, {}): {:?}", density, x, y, d
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn zncc_rejects_flat_right_blocks() {
+    // The right image is uniform gray left of column 8.
+    let right = |x: usize, y: usize| if x < 8 { 100 } else { common::texture(x, y) };
+    let pair = common::pair_from((WIDTH, HEIGHT), |x, y| right(x.saturating_sub(SHIFT), y), right);
+    let disparity = StereoMatcher::new()
+        .block_size(3, 3)
+        .max_disparity(6)
+        .cost_function(CostFunction::Zncc)
+        .compute(&pair)
+        .unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..7 {
+            assert_eq!(disparity.get(x, y), None, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn zncc_never_prefers_flat_left_blocks() {
+    // Rows brighten downwards on the right and darken downwards on the left,
+    // so every textured block anticorrelates, and the left image is uniform
+    // gray from column 12 on.
+    let right: Vec<u8> = (0..WIDTH * HEIGHT).map(|i| (i / WIDTH * 20) as u8).collect();
+    let left: Vec<u8> = (0..WIDTH * HEIGHT)
+        .map(|i| if i % WIDTH < 12 { ((HEIGHT - 1 - i / WIDTH) * 20) as u8 } else { 100 })
+        .collect();
+    let pair = StereoPair::from_gray_pixels(left, right, WIDTH, HEIGHT).unwrap();
+    let disparity = StereoMatcher::new()
+        .block_size(3, 3)
+        .max_disparity(6)
+        .cost_function(CostFunction::Zncc)
+        .compute(&pair)
+        .unwrap();
+    // Past column 10 every shifted block is flat.
+    for y in 0..HEIGHT {
+        for x in 1..11 {
+            if let Some(d) = disparity.get(x, y) {
+                assert!(x + d as usize - 1 < 12, "({}, {}) matched a flat block at {}", x, y, d);
+            }
+        }
+    }
+}
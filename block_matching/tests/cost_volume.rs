@@ -0,0 +1,79 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{
+    Aggregation, BorderPolicy, CostFunction, CostVolume, Density, MatchError, StereoMatcher, StereoPair,
+};
+
+const WIDTH: usize = 20;
+const HEIGHT: usize = 10;
+
+fn textured_pair() -> StereoPair {
+    common::textured_pair((WIDTH, HEIGHT))
+}
+
+// Only the OpenCL backend streams costs.
+fn assert_same_when_streamed(pair: &StereoPair, matcher: StereoMatcher, context: &str) {
+    let stored = match common::compute_on_opencl(matcher.clone().cost_volume(CostVolume::Stored), pair, context) {
+        Some(stored) => stored,
+        None => return,
+    };
+    let streamed = common::compute_on_opencl(matcher.cost_volume(CostVolume::Streamed), pair, context).unwrap();
+    assert!(stored.data == streamed.data, "{}", context);
+}
+
+fn matcher() -> StereoMatcher {
+    StereoMatcher::new().block_size(3, 3).min_disparity(1).max_disparity(7)
+}
+
+#[test]
+fn streamed_costs_match_stored_costs() {
+    let pair = textured_pair();
+    for &cost_function in CostFunction::all().iter() {
+        for &density in Density::all().iter() {
+            assert_same_when_streamed(
+                &pair,
+                matcher().cost_function(cost_function).density(density),
+                &format!("cost {}, density {}", cost_function, density),
+            );
+        }
+    }
+    for &border in BorderPolicy::all().iter() {
+        assert_same_when_streamed(&pair, matcher().border(border), &format!("border {}", border));
+    }
+}
+
+#[test]
+fn streamed_adaptive_weights_match_stored() {
+    let pair = textured_pair();
+    for &border in [BorderPolicy::Clamp, BorderPolicy::Invalid].iter() {
+        assert_same_when_streamed(
+            &pair,
+            matcher().aggregation(Aggregation::AdaptiveWeights).border(border),
+            &format!("border {}", border),
+        );
+    }
+}
+
+#[test]
+fn streamed_cross_regions_match_stored() {
+    let pair = textured_pair();
+    for &border in [BorderPolicy::Clamp, BorderPolicy::Invalid].iter() {
+        assert_same_when_streamed(
+            &pair,
+            matcher().aggregation(Aggregation::Cross).cross_arms(60, 3).border(border),
+            &format!("border {}", border),
+        );
+    }
+}
+
+#[test]
+fn guided_filter_needs_stored_costs() {
+    let pair = textured_pair();
+    let matcher = matcher().aggregation(Aggregation::Guided).cost_volume(CostVolume::Streamed);
+    match matcher.compute(&pair) {
+        Err(MatchError::InvalidParameter(_)) => {}
+        other => panic!("{:?} gave {:?}", matcher, other.map(|disparity| disparity.width)),
+    }
+}
@@ -0,0 +1,318 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{
+    Aggregation, Backend, BorderPolicy, ConfidenceMeasure, CostFunction, Density, DisparityData, DisparityType,
+    SgmPaths, StereoMatcher, StereoPair, SubpixelFit,
+};
+use common::texture;
+
+const WIDTH: usize = 20;
+const HEIGHT: usize = 10;
+const SHIFT: usize = 3;
+/// Relative difference allowed between float results, which the device's
+/// `sqrt` and `exp` may round differently from the host's.
+const TOLERANCE: f32 = 1e-4;
+
+fn shifted_pair() -> StereoPair {
+    common::shifted_pair((WIDTH, HEIGHT), SHIFT)
+}
+
+fn textured_pair() -> StereoPair {
+    common::textured_pair((WIDTH, HEIGHT))
+}
+
+// textured_pair in color, with channels that differ in texture.
+fn textured_color_pair() -> StereoPair {
+    let color = |x: usize, y: usize| [texture(x, y), texture(y, x), texture(x + y, x)];
+    common::color_pair_from((WIDTH, HEIGHT), |x, y| color(x.saturating_sub(2 + y % 3), y), color)
+}
+
+/// Without an OpenCL platform there is nothing to compare against.
+fn assert_same_on_cpu(pair: &StereoPair, matcher: StereoMatcher, context: &str) {
+    let opencl = match common::compute_on_opencl(matcher.clone(), pair, context) {
+        Some(opencl) => opencl,
+        None => return,
+    };
+    let cpu = matcher.backend(Backend::Cpu).compute(pair).unwrap();
+    assert_eq!((cpu.width, cpu.height), (opencl.width, opencl.height), "{}", context);
+    match (&cpu.data, &opencl.data) {
+        (DisparityData::F32(cpu_data), DisparityData::F32(opencl_data)) => assert_close(cpu_data, opencl_data, context),
+        (cpu_data, opencl_data) => assert!(cpu_data == opencl_data, "{}", context),
+    }
+    match (&cpu.confidence, &opencl.confidence) {
+        (Some(cpu_confidence), Some(opencl_confidence)) => {
+            assert_close(&cpu_confidence.data, &opencl_confidence.data, context)
+        }
+        (cpu_confidence, opencl_confidence) => assert!(cpu_confidence == opencl_confidence, "{}", context),
+    }
+}
+
+fn assert_close(cpu: &[f32], opencl: &[f32], context: &str) {
+    assert_eq!(cpu.len(), opencl.len(), "{}", context);
+    for (i, (&c, &o)) in cpu.iter().zip(opencl).enumerate() {
+        assert!((c - o).abs() <= TOLERANCE * o.abs().max(1.0), "{} at {}: {} on the CPU, {} on OpenCL", context, i, c, o);
+    }
+}
+
+fn matcher() -> StereoMatcher {
+    StereoMatcher::new().block_size(4, 3).min_disparity(1).max_disparity(8).border_value(200)
+}
+
+fn assert_finds_shift_on_cpu(pair: &StereoPair, matcher: StereoMatcher, context: &str) {
+    let disparity = matcher.backend(Backend::Cpu).compute(pair).unwrap();
+    // Away from the borders, where every block and its guided filter window
+    // lie inside both images at each searched disparity.
+    for y in 2..HEIGHT - 2 {
+        for x in 4..WIDTH - 12 {
+            assert_eq!(disparity.get(x, y), Some(SHIFT as f32), "{} at ({}, {})", context, x, y);
+        }
+    }
+}
+
+#[test]
+fn cpu_finds_known_shift() {
+    let pair = shifted_pair();
+    // Birchfield-Tomasi's half-pixel interpolation lets a neighboring
+    // disparity tie on this sharp texture.
+    for &cost_function in CostFunction::all().iter().filter(|&&c| c != CostFunction::BirchfieldTomasi) {
+        assert_finds_shift_on_cpu(&pair, matcher().cost_function(cost_function), &format!("cost {}", cost_function));
+    }
+    for &aggregation in Aggregation::all().iter() {
+        assert_finds_shift_on_cpu(&pair, matcher().aggregation(aggregation), &format!("aggregation {}", aggregation));
+    }
+    for &disparity_type in DisparityType::all().iter() {
+        assert_finds_shift_on_cpu(&pair, matcher().disparity_type(disparity_type), &format!("type {}", disparity_type));
+    }
+}
+
+#[test]
+fn cpu_matches_opencl() {
+    let pair = textured_pair();
+    for &cost_function in CostFunction::all().iter() {
+        for &density in Density::all().iter() {
+            for &border in BorderPolicy::all().iter() {
+                assert_same_on_cpu(
+                    &pair,
+                    matcher().cost_function(cost_function).density(density).border(border),
+                    &format!("cost {}, density {}, border {}", cost_function, density, border),
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_wide_transform_windows() {
+    // Census bits need a u64 past 32 pixels.
+    let pair = textured_pair();
+    for &cost_function in [CostFunction::Census, CostFunction::Rank].iter() {
+        for &density in Density::all().iter() {
+            for &border in BorderPolicy::all().iter() {
+                assert_same_on_cpu(
+                    &pair,
+                    matcher().cost_function(cost_function).transform_window(7, 9).density(density).border(border),
+                    &format!("cost {}, density {}, border {}", cost_function, density, border),
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_for_each_disparity_type() {
+    let pair = textured_pair();
+    for &disparity_type in DisparityType::all().iter() {
+        assert_same_on_cpu(
+            &pair,
+            matcher().border(BorderPolicy::Invalid).disparity_type(disparity_type),
+            &format!("type {}", disparity_type),
+        );
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_sgm() {
+    let pair = textured_pair();
+    for &cost_function in CostFunction::all().iter().filter(|&&c| c != CostFunction::Zncc) {
+        assert_same_on_cpu(
+            &pair,
+            matcher().aggregation(Aggregation::Sgm).cost_function(cost_function),
+            &format!("cost {}", cost_function),
+        );
+    }
+    for &sgm_paths in SgmPaths::all().iter() {
+        for &border in [BorderPolicy::Clamp, BorderPolicy::Invalid].iter() {
+            assert_same_on_cpu(
+                &pair,
+                matcher().aggregation(Aggregation::Sgm).sgm_paths(sgm_paths).sgm_penalties(3, 40).border(border),
+                &format!("paths {}, border {}", sgm_paths, border),
+            );
+        }
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_adaptive_weights() {
+    for pair in [textured_pair(), textured_color_pair()].iter() {
+        for &border in BorderPolicy::all().iter() {
+            assert_same_on_cpu(
+                pair,
+                matcher().aggregation(Aggregation::AdaptiveWeights).border(border),
+                &format!("color {}, border {}", pair.color.is_some(), border),
+            );
+        }
+    }
+    for &cost_function in CostFunction::all().iter().filter(|&&c| c != CostFunction::Zncc) {
+        assert_same_on_cpu(
+            &textured_color_pair(),
+            matcher().aggregation(Aggregation::AdaptiveWeights).adaptive_weights(20.0, 3.0).cost_function(cost_function),
+            &format!("cost {}", cost_function),
+        );
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_cross_regions() {
+    for pair in [textured_pair(), textured_color_pair()].iter() {
+        for &border in BorderPolicy::all().iter() {
+            assert_same_on_cpu(
+                pair,
+                matcher().aggregation(Aggregation::Cross).cross_arms(60, 3).border(border),
+                &format!("color {}, border {}", pair.color.is_some(), border),
+            );
+        }
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_guided_filter() {
+    let pair = textured_pair();
+    for &border in BorderPolicy::all().iter() {
+        assert_same_on_cpu(
+            &pair,
+            matcher().aggregation(Aggregation::Guided).border(border),
+            &format!("border {}", border),
+        );
+    }
+    for &cost_function in CostFunction::all().iter().filter(|&&c| c != CostFunction::Zncc) {
+        assert_same_on_cpu(
+            &pair,
+            matcher().aggregation(Aggregation::Guided).guided_filter_epsilon(500.0).cost_function(cost_function),
+            &format!("cost {}", cost_function),
+        );
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_integral_images() {
+    let pair = textured_pair();
+    for &density in Density::all().iter() {
+        for &border in BorderPolicy::all().iter() {
+            assert_same_on_cpu(
+                &pair,
+                matcher().aggregation(Aggregation::IntegralBox).density(density).border(border),
+                &format!("density {}, border {}", density, border),
+            );
+        }
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_subpixel_fits() {
+    let pair = textured_color_pair();
+    for &subpixel_fit in SubpixelFit::all().iter() {
+        for &aggregation in Aggregation::all().iter() {
+            assert_same_on_cpu(
+                &pair,
+                matcher().aggregation(aggregation).subpixel_fit(subpixel_fit).border(BorderPolicy::Invalid),
+                &format!("fit {}, aggregation {}", subpixel_fit, aggregation),
+            );
+        }
+        for &cost_function in CostFunction::all().iter() {
+            assert_same_on_cpu(
+                &pair,
+                matcher().cost_function(cost_function).subpixel_fit(subpixel_fit),
+                &format!("fit {}, cost {}", subpixel_fit, cost_function),
+            );
+        }
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_rejection() {
+    let pair = textured_color_pair();
+    for &aggregation in Aggregation::all().iter() {
+        assert_same_on_cpu(
+            &pair,
+            matcher().aggregation(aggregation).uniqueness_ratio(5).subpixel_fit(SubpixelFit::Parabola),
+            &format!("aggregation {}", aggregation),
+        );
+    }
+    for &density in Density::all().iter() {
+        assert_same_on_cpu(
+            &pair,
+            matcher().density(density).uniqueness_ratio(15).texture_threshold(2000),
+            &format!("density {}", density),
+        );
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_confidence() {
+    let pair = textured_color_pair();
+    for &measure in ConfidenceMeasure::all().iter() {
+        for &aggregation in Aggregation::all().iter() {
+            assert_same_on_cpu(
+                &pair,
+                matcher().aggregation(aggregation).confidence(measure),
+                &format!("measure {}, aggregation {}", measure, aggregation),
+            );
+        }
+    }
+    for &density in Density::all().iter() {
+        assert_same_on_cpu(
+            &pair,
+            matcher()
+                .density(density)
+                .uniqueness_ratio(15)
+                .texture_threshold(2000)
+                .confidence(ConfidenceMeasure::Ratio),
+            &format!("density {}", density),
+        );
+    }
+}
+
+#[test]
+fn cpu_matches_opencl_with_filters() {
+    let pair = textured_pair();
+    for &density in Density::all().iter() {
+        for &disparity_type in DisparityType::all().iter() {
+            assert_same_on_cpu(
+                &pair,
+                matcher()
+                    .density(density)
+                    .disparity_type(disparity_type)
+                    .median_filter(3)
+                    .speckle_filter(4, 1.0)
+                    .confidence(ConfidenceMeasure::Ratio),
+                &format!("density {}, type {}", density, disparity_type),
+            );
+        }
+    }
+    assert_same_on_cpu(
+        &pair,
+        matcher().block_size(1, 1).subpixel_fit(SubpixelFit::Parabola).speckle_filter(6, 0.5),
+        "subpixel",
+    );
+    assert_same_on_cpu(
+        &pair,
+        matcher()
+            .left_right_check(1.0)
+            .median_filter(3)
+            .speckle_filter(4, 1.0)
+            .confidence(ConfidenceMeasure::LeftRight),
+        "left-right",
+    );
+}
@@ -0,0 +1,49 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{Density, DispatchStrategy, StereoMatcher, StereoPair};
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 12;
+const SHIFT: usize = 3;
+
+fn shifted_pair() -> StereoPair {
+    common::shifted_pair((WIDTH, HEIGHT), SHIFT)
+}
+
+#[test]
+fn dense_gives_a_disparity_per_pixel() {
+    let pair = shifted_pair();
+    for &dispatch in DispatchStrategy::all().iter() {
+        let disparity = StereoMatcher::new()
+            .block_size(5, 3)
+            .max_disparity(6)
+            .dispatch(dispatch)
+            .compute(&pair)
+            .unwrap();
+        assert_eq!((disparity.width, disparity.height), (WIDTH, HEIGHT));
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH - SHIFT - 2 {
+                assert_eq!(disparity.get(x, y), Some(SHIFT as f32), "dispatch {}, ({}, {})", dispatch, x, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn tiled_gives_a_disparity_per_block() {
+    let pair = shifted_pair();
+    let disparity = StereoMatcher::new()
+        .block_size(5, 3)
+        .max_disparity(6)
+        .density(Density::Tiled)
+        .compute(&pair)
+        .unwrap();
+    assert_eq!((disparity.width, disparity.height), (WIDTH / 5, HEIGHT / 3));
+    for y in 0..HEIGHT / 3 {
+        for x in 0..(WIDTH - SHIFT) / 5 {
+            assert_eq!(disparity.get(x, y), Some(SHIFT as f32), "({}, {})", x, y);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{ColorMap, DisparityData, DisparityMap, DisparityType, MatchError, StereoMatcher, StereoPair};
+
+const WIDTH: usize = 300;
+const HEIGHT: usize = 8;
+const SHIFT: usize = 280;
+
+// Left is the right image moved SHIFT pixels to the right, so the first
+// WIDTH - SHIFT right pixels match at a disparity past any u8.
+fn widely_shifted_pair() -> StereoPair {
+    common::shifted_pair((WIDTH, HEIGHT), SHIFT)
+}
+
+fn matcher() -> StereoMatcher {
+    StereoMatcher::new().block_size(3, 3).min_disparity(SHIFT - 10).max_disparity(SHIFT + 10)
+}
+
+#[test]
+fn wide_types_keep_disparities_past_255() {
+    let pair = widely_shifted_pair();
+    for &disparity_type in [DisparityType::U16, DisparityType::F32].iter() {
+        let disparity = matcher().disparity_type(disparity_type).compute(&pair).unwrap();
+        assert_eq!(disparity.data.disparity_type(), disparity_type);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH - SHIFT - 1 {
+                assert_eq!(disparity.get(x, y), Some(SHIFT as f32), "{} ({}, {})", disparity_type, x, y);
+            }
+        }
+    }
+    // Without a type, the narrowest one that holds the range.
+    let disparity = matcher().compute(&pair).unwrap();
+    assert_eq!(disparity.data.disparity_type(), DisparityType::U16);
+}
+
+#[test]
+fn u8_rejects_disparities_past_255() {
+    let pair = widely_shifted_pair();
+    let matcher = matcher().disparity_type(DisparityType::U8);
+    match matcher.compute(&pair) {
+        Err(MatchError::InvalidParameter(_)) => {}
+        other => panic!("{:?} gave {:?}", matcher, other.map(|disparity| disparity.width)),
+    }
+}
+
+#[test]
+fn empty_range_has_no_colors() {
+    for &(min_disparity, max_disparity) in [(4, 4), (5, 4)].iter() {
+        let disparity = DisparityMap {
+            width: 2,
+            height: 1,
+            min_disparity,
+            max_disparity,
+            data: DisparityData::U8(vec![4, 4]),
+            confidence: None,
+        };
+        match disparity.to_rgb_image(ColorMap::Gray) {
+            Err(MatchError::InvalidParameter(_)) => {}
+            other => panic!("{}..{} gave {:?}", min_disparity, max_disparity, other.map(|image| image.width())),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{
+    ConfidenceMeasure, CostFunction, CostVolume, Density, DispatchStrategy, StereoMatcher, StereoPair,
+};
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 12;
+const SHIFT: usize = 3;
+
+fn shifted_pair() -> StereoPair {
+    common::shifted_pair((WIDTH, HEIGHT), SHIFT)
+}
+
+#[test]
+fn strategies_give_the_same_map() {
+    let pair = shifted_pair();
+    for &cost_function in CostFunction::all().iter() {
+        for &density in Density::all().iter() {
+            let matcher = StereoMatcher::new()
+                .block_size(3, 3)
+                .max_disparity(6)
+                .cost_function(cost_function)
+                .density(density)
+                .cost_volume(CostVolume::Stored);
+            let matcher = if cost_function == CostFunction::Zncc {
+                matcher
+            } else {
+                matcher.confidence(ConfidenceMeasure::Ratio)
+            };
+            // Only the OpenCL backend dispatches work groups.
+            let expected = match common::compute_on_opencl(
+                matcher.clone().dispatch(DispatchStrategy::default()), &pair, "dispatch strategies"
+            ) {
+                Some(expected) => expected,
+                None => return,
+            };
+            for &dispatch in DispatchStrategy::all().iter() {
+                let context = format!("{} {} {}", cost_function, density, dispatch);
+                let disparity = common::compute_on_opencl(matcher.clone().dispatch(dispatch), &pair, &context).unwrap();
+                assert_eq!(disparity.data, expected.data, "{}", context);
+                assert_eq!(disparity.confidence, expected.confidence, "{}", context);
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+extern crate block_matching;
+
+use block_matching::{ColorMap, DisparityData, DisparityMap, MatchError, OutputFormat, StereoPair};
+
+const MISSING_DIR: &str = "/nonexistent/block_matching";
+
+#[test]
+fn missing_image_is_an_image_error() {
+    let left = format!("{}/left.png", MISSING_DIR);
+    match StereoPair::open(&left, &format!("{}/right.png", MISSING_DIR)) {
+        Err(MatchError::Image { ref file_name, .. }) if *file_name == left => {}
+        other => panic!("{} gave {:?}", left, other.map(|pair| pair.width)),
+    }
+}
+
+#[test]
+fn unwritable_output_is_an_output_error() {
+    let disparity = DisparityMap {
+        width: 2,
+        height: 2,
+        min_disparity: 0,
+        max_disparity: 4,
+        data: DisparityData::U8(vec![0, 1, 2, 3]),
+        confidence: None,
+    };
+    let formats = [(OutputFormat::Image, "png"), (OutputFormat::Pgm, "pgm"), (OutputFormat::Pfm, "pfm")];
+    for &(format, extension) in formats.iter() {
+        let output = format!("{}/disparity.{}", MISSING_DIR, extension);
+        match disparity.save(&output, format, ColorMap::Gray) {
+            Err(MatchError::Output { ref file_name, .. }) if *file_name == output => {}
+            other => panic!("{} gave {:?}", output, other),
+        }
+    }
+}
+
+#[test]
+fn kernel_build_error_shows_build_log() {
+    let log = "<kernel>:12:5: error: use of undeclared identifier 'DIFF_LEN'";
+    let message = MatchError::KernelBuild(log.to_string()).to_string();
+    assert!(message.ends_with(log), "{}", message);
+}
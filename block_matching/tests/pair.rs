@@ -0,0 +1,40 @@
+extern crate block_matching;
+
+use block_matching::{MatchError, SizePolicy, StereoPair};
+
+/// Distinct gray levels for a `width` x `height` image.
+fn numbered(width: usize, height: usize) -> Vec<u8> {
+    (0..width * height).map(|i| i as u8).collect()
+}
+
+#[test]
+fn strict_policy_rejects_mismatched_sizes() {
+    for &(right_width, right_height) in [(3, 3), (4, 2), (5, 4)].iter() {
+        let right = numbered(right_width, right_height);
+        let pair = StereoPair::from_gray_images(
+            numbered(4, 3), (4, 3), right, (right_width, right_height), SizePolicy::Strict
+        );
+        match pair {
+            Err(MatchError::SizeMismatch { left, right }) => {
+                assert_eq!(left, (4, 3));
+                assert_eq!(right, (right_width, right_height));
+            }
+            other => panic!("{}x{} gave {:?}", right_width, right_height, other.map(|pair| pair.width)),
+        }
+    }
+}
+
+#[test]
+fn crop_or_pad_fits_right_image_to_left() {
+    // Padding repeats the last column and the last row.
+    let pair = StereoPair::from_gray_images(numbered(4, 3), (4, 3), numbered(3, 2), (3, 2), SizePolicy::CropOrPad)
+        .unwrap();
+    assert_eq!((pair.width, pair.height), (4, 3));
+    assert_eq!(pair.right, vec![0, 1, 2, 2, 3, 4, 5, 5, 3, 4, 5, 5]);
+
+    let pair = StereoPair::from_gray_images(numbered(4, 3), (4, 3), numbered(5, 4), (5, 4), SizePolicy::CropOrPad)
+        .unwrap();
+    assert_eq!((pair.width, pair.height), (4, 3));
+    assert_eq!(pair.right, vec![0, 1, 2, 3, 5, 6, 7, 8, 10, 11, 12, 13]);
+    assert_eq!(pair.left, numbered(4, 3));
+}
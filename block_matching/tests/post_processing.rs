@@ -0,0 +1,134 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{DisparityMap, MatchError, StereoMatcher, StereoPair};
+use common::Rect;
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 16;
+const SHIFT: usize = 2;
+const FOREGROUND_SHIFT: usize = 6;
+
+// A 3x3 square in front of the background, small enough to be a speck.
+const FOREGROUND: Rect = (12, 15, 6, 9);
+
+fn is_foreground(x: usize, y: usize) -> bool {
+    common::contains(FOREGROUND, x, y)
+}
+
+fn pair_with_speck() -> StereoPair {
+    common::pair_with_foreground((WIDTH, HEIGHT), FOREGROUND, (SHIFT, FOREGROUND_SHIFT))
+}
+
+fn matcher() -> StereoMatcher {
+    StereoMatcher::new().block_size(1, 1).max_disparity(8)
+}
+
+// Median of the valid disparities of `map` in the `size` square around
+// (x, y), clipped to the map.
+fn window_median(map: &DisparityMap, x: usize, y: usize, size: usize) -> f32 {
+    let mut window = vec![];
+    for window_y in y.saturating_sub(size / 2)..(y + size / 2 + 1).min(map.height) {
+        for window_x in x.saturating_sub(size / 2)..(x + size / 2 + 1).min(map.width) {
+            window.extend(map.get(window_x, window_y));
+        }
+    }
+    window.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    window[window.len() / 2]
+}
+
+#[test]
+fn median_filter_takes_window_medians() {
+    let pair = pair_with_speck();
+    let unfiltered = matcher().compute(&pair).unwrap();
+    let filtered = matcher().median_filter(5).compute(&pair).unwrap();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let expected = unfiltered.get(x, y).map(|_| window_median(&unfiltered, x, y, 5));
+            assert_eq!(filtered.get(x, y), expected, "({}, {})", x, y);
+        }
+    }
+    // The 3x3 square is too small to hold the median of 5x5 windows.
+    assert!((0..WIDTH * HEIGHT).all(|i| filtered.get(i % WIDTH, i / WIDTH) != Some(FOREGROUND_SHIFT as f32)));
+}
+
+#[test]
+fn speckle_filter_removes_small_regions() {
+    let pair = pair_with_speck();
+    let unfiltered = matcher().compute(&pair).unwrap();
+    let kept = matcher().speckle_filter(8, 1.0).compute(&pair).unwrap();
+    let removed = matcher().speckle_filter(9, 1.0).compute(&pair).unwrap();
+    for y in 0..HEIGHT {
+        for x in (0..WIDTH).filter(|&x| is_foreground(x, y)) {
+            assert_eq!(unfiltered.get(x, y), Some(FOREGROUND_SHIFT as f32), "({}, {})", x, y);
+            assert_eq!(kept.get(x, y), Some(FOREGROUND_SHIFT as f32), "({}, {})", x, y);
+            assert_eq!(removed.get(x, y), None, "({}, {})", x, y);
+        }
+    }
+    let background = (0..WIDTH * HEIGHT)
+        .filter(|&i| removed.get(i % WIDTH, i / WIDTH) == Some(SHIFT as f32))
+        .count();
+    assert!(background > WIDTH * HEIGHT * 3 / 4, "background {}", background);
+}
+
+// Size of the region of valid disparities connected to (x, y) through
+// neighbors at most max_difference apart.
+fn region_size(map: &DisparityMap, x: usize, y: usize, max_difference: f32) -> usize {
+    let mut region = vec![(x, y)];
+    let mut next = 0;
+    while next < region.len() {
+        let (x, y) = region[next];
+        next += 1;
+        let disparity = map.get(x, y).unwrap();
+        let neighbors = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+        for &(neighbor_x, neighbor_y) in neighbors.iter() {
+            if neighbor_x >= map.width || neighbor_y >= map.height || region.contains(&(neighbor_x, neighbor_y)) {
+                continue;
+            }
+            if map.get(neighbor_x, neighbor_y).is_some_and(|neighbor| (neighbor - disparity).abs() <= max_difference) {
+                region.push((neighbor_x, neighbor_y));
+            }
+        }
+    }
+    region.len()
+}
+
+#[test]
+fn speckle_filter_removes_regions_left_by_left_right_check() {
+    let pair = pair_with_speck();
+    let checked = matcher().left_right_check(0.0).compute(&pair).unwrap();
+    let filtered = matcher().left_right_check(0.0).speckle_filter(2, 1.0).compute(&pair).unwrap();
+    let mut removed = 0;
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            match checked.get(x, y) {
+                Some(disparity) if region_size(&checked, x, y, 1.0) > 2 => {
+                    assert_eq!(filtered.get(x, y), Some(disparity), "({}, {})", x, y);
+                }
+                _ => {
+                    assert_eq!(filtered.get(x, y), None, "({}, {})", x, y);
+                    removed += checked.get(x, y).map_or(0, |_| 1);
+                }
+            }
+        }
+    }
+    // Fragments the check cuts off from larger regions.
+    assert!(removed > 0);
+}
+
+#[test]
+fn filters_reject_unsupported_settings() {
+    let pair = pair_with_speck();
+    for matcher in [
+        matcher().median_filter(4),
+        matcher().median_filter(13),
+        matcher().speckle_filter(10, -1.0),
+    ].iter() {
+        match matcher.compute(&pair) {
+            Err(MatchError::InvalidParameter(_)) => {}
+            other => panic!("{:?} gave {:?}", matcher, other.map(|disparity| disparity.width)),
+        }
+    }
+}
+
@@ -0,0 +1,90 @@
+extern crate block_matching;
+
+mod common;
+
+use block_matching::{CostFunction, Density, MatchError, StereoMatcher, StereoPair};
+use common::Rect;
+
+const WIDTH: usize = 24;
+const HEIGHT: usize = 16;
+const SHIFT: usize = 3;
+
+const FLAT: Rect = (8, 16, 4, 12);
+
+fn is_flat(x: usize, y: usize) -> bool {
+    common::contains(FLAT, x, y)
+}
+
+fn pair_with_flat_patch() -> StereoPair {
+    common::pair_with_flat_patch((WIDTH, HEIGHT), FLAT, SHIFT)
+}
+
+fn assert_flat_blocks_invalid(matcher: StereoMatcher) {
+    let disparity = matcher.compute(&pair_with_flat_patch()).unwrap();
+    for y in 1..HEIGHT - 1 {
+        for x in 1..WIDTH - SHIFT - 1 {
+            if common::is_flat_block(FLAT, x, y) {
+                assert_eq!(disparity.get(x, y), None, "({}, {})", x, y);
+            } else if !is_flat(x, y) {
+                assert_eq!(disparity.get(x, y), Some(SHIFT as f32), "({}, {})", x, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn uniqueness_ratio_rejects_ambiguous_matches() {
+    assert_flat_blocks_invalid(StereoMatcher::new().block_size(3, 3).max_disparity(8).uniqueness_ratio(10));
+}
+
+// One row where the first right pixel costs `low` at disparity 2 and `high`
+// at disparity 5, and far more elsewhere.
+fn pair_with_two_matches(low: u8, high: u8) -> StereoPair {
+    let right = vec![100; 16];
+    let mut left = vec![200; 16];
+    left[2] = 100 + low;
+    left[5] = 100 + high;
+    StereoPair::from_gray_pixels(left, right, 16, 1).unwrap()
+}
+
+#[test]
+fn uniqueness_ratio_keeps_matches_exactly_the_ratio_apart() {
+    let matcher = StereoMatcher::new().block_size(1, 1).max_disparity(8);
+    let pair = pair_with_two_matches(10, 11);
+    assert_eq!(matcher.clone().uniqueness_ratio(10).compute(&pair).unwrap().get(0, 0), Some(2.0));
+    assert_eq!(matcher.uniqueness_ratio(11).compute(&pair).unwrap().get(0, 0), None);
+}
+
+#[test]
+fn uniqueness_ratio_rejects_ties_at_zero_cost() {
+    let matcher = StereoMatcher::new().block_size(1, 1).max_disparity(8).uniqueness_ratio(10);
+    assert_eq!(matcher.clone().compute(&pair_with_two_matches(0, 0)).unwrap().get(0, 0), None);
+    assert_eq!(matcher.compute(&pair_with_two_matches(0, 1)).unwrap().get(0, 0), Some(2.0));
+}
+
+#[test]
+fn texture_threshold_rejects_flat_blocks() {
+    assert_flat_blocks_invalid(StereoMatcher::new().block_size(3, 3).max_disparity(8).texture_threshold(1));
+    let tiled = StereoMatcher::new()
+        .block_size(4, 4)
+        .max_disparity(8)
+        .density(Density::Tiled)
+        .texture_threshold(1)
+        .compute(&pair_with_flat_patch())
+        .unwrap();
+    for y in 0..HEIGHT / 4 {
+        for x in 0..WIDTH / 4 - 1 {
+            let flat = is_flat(x * 4, y * 4) && is_flat(x * 4 + 3, y * 4 + 3);
+            assert_eq!(tiled.get(x, y).is_none(), flat, "tile ({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn uniqueness_ratio_rejects_zncc() {
+    let matcher = StereoMatcher::new().max_disparity(8).cost_function(CostFunction::Zncc).uniqueness_ratio(10);
+    match matcher.compute(&pair_with_flat_patch()) {
+        Err(MatchError::InvalidParameter(_)) => {}
+        other => panic!("ZNCC gave {:?}", other.map(|disparity| disparity.width)),
+    }
+}
@@ -0,0 +1,75 @@
+extern crate block_matching;
+
+use block_matching::{
+    Aggregation, CostFunction, DisparityType, MatchError, StereoMatcher, StereoPair, SubpixelFit,
+};
+
+const WIDTH: usize = 32;
+const HEIGHT: usize = 16;
+const SHIFT: f32 = 2.4;
+
+// Left is a smooth right image moved SHIFT pixels to the right, so that no
+// integer disparity matches exactly.
+fn fractionally_shifted_pair() -> StereoPair {
+    let texture = |x: f32, y: f32| 128.0 + 60.0 * (0.5 * x + 1.3 * y).sin() + 40.0 * (0.23 * x - 0.7 * y).cos();
+    let pixels = |shift: f32| -> Vec<u8> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| texture((i % WIDTH) as f32 - shift, (i / WIDTH) as f32).round() as u8)
+            .collect()
+    };
+    StereoPair::from_gray_pixels(pixels(SHIFT), pixels(0.0), WIDTH, HEIGHT).unwrap()
+}
+
+// Mean distance from SHIFT away from the right edge, where the shifted
+// blocks leave the left image.
+fn mean_error(matcher: StereoMatcher) -> f32 {
+    let disparity = matcher.compute(&fractionally_shifted_pair()).unwrap();
+    let xs = 0..WIDTH - 8;
+    let count = xs.len() * HEIGHT;
+    let error: f32 = xs
+        .flat_map(|x| (0..HEIGHT).map(move |y| (x, y)))
+        .map(|(x, y)| (disparity.get(x, y).unwrap() - SHIFT).abs())
+        .sum();
+    error / count as f32
+}
+
+#[test]
+fn fits_recover_fractional_disparity() {
+    let matcher = StereoMatcher::new().block_size(7, 7).max_disparity(6);
+    let whole = mean_error(matcher.clone().cost_function(CostFunction::Ssd));
+    assert!(whole > 0.3, "whole {}", whole);
+    let parabola = mean_error(matcher.clone().cost_function(CostFunction::Ssd).subpixel_fit(SubpixelFit::Parabola));
+    assert!(parabola < 0.1, "parabola {}", parabola);
+    let equiangular = mean_error(matcher.subpixel_fit(SubpixelFit::Equiangular));
+    assert!(equiangular < 0.1, "equiangular {}", equiangular);
+}
+
+#[test]
+fn fits_refine_every_aggregation() {
+    let pair = fractionally_shifted_pair();
+    for &aggregation in Aggregation::all().iter() {
+        let disparity = StereoMatcher::new()
+            .block_size(7, 7)
+            .max_disparity(6)
+            .aggregation(aggregation)
+            .subpixel_fit(SubpixelFit::Parabola)
+            .compute(&pair)
+            .unwrap();
+        assert_eq!(disparity.data.disparity_type(), DisparityType::F32);
+        assert!(
+            (0..WIDTH * HEIGHT).any(|i| disparity.data.get(i).is_some_and(|d| d.fract() != 0.0)),
+            "aggregation {}", aggregation
+        );
+    }
+}
+
+#[test]
+fn fits_need_f32_output() {
+    let pair = fractionally_shifted_pair();
+    let matcher = StereoMatcher::new().max_disparity(6).subpixel_fit(SubpixelFit::Parabola);
+    match matcher.clone().disparity_type(DisparityType::U8).compute(&pair) {
+        Err(MatchError::InvalidParameter(_)) => {}
+        other => panic!("u8 output gave {:?}", other.map(|disparity| disparity.width)),
+    }
+    assert!(matcher.disparity_type(DisparityType::F32).compute(&pair).is_ok());
+}
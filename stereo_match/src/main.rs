@@ -0,0 +1,353 @@
+extern crate block_matching;
+#[macro_use]
+extern crate clap;
+extern crate time;
+use block_matching::{
+    Aggregation, Backend, BorderPolicy, ColorMap, ConfidenceMeasure, CostFunction, CostVolume, Density, DisparityType,
+    DispatchStrategy, MatchError, OutputFormat, SgmPaths, SizePolicy, StereoMatcher, StereoPair, SubpixelFit,
+};
+use clap::{App, Arg};
+use time::PreciseTime;
+
+fn is_positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("{} is not a positive integer", value)),
+    }
+}
+
+fn is_non_negative(value: String) -> Result<(), String> {
+    value.parse::<usize>().map(|_| ()).map_err(|_| format!("{} is not a non-negative integer", value))
+}
+
+fn exit_with(error: MatchError) -> ! {
+    eprintln!("error: {}", error);
+    if let MatchError::SizeMismatch { .. } = error {
+        eprintln!("pass --size-policy crop-or-pad to fit the right image to the left one");
+    }
+    std::process::exit(1)
+}
+
+fn main() {
+    let density_names: Vec<&str> = Density::all().iter().map(|d| d.name()).collect();
+    let cost_function_names: Vec<&str> = CostFunction::all().iter().map(|c| c.name()).collect();
+    let aggregation_names: Vec<&str> = Aggregation::all().iter().map(|a| a.name()).collect();
+    let sgm_paths_names: Vec<&str> = SgmPaths::all().iter().map(|p| p.name()).collect();
+    let backend_names: Vec<&str> = Backend::all().iter().map(|b| b.name()).collect();
+    let cost_volume_names: Vec<&str> = CostVolume::all().iter().map(|v| v.name()).collect();
+    let dispatch_names: Vec<&str> = DispatchStrategy::all().iter().map(|d| d.name()).collect();
+    let border_names: Vec<&str> = BorderPolicy::all().iter().map(|b| b.name()).collect();
+    let format_names: Vec<&str> = OutputFormat::all().iter().map(|f| f.name()).collect();
+    let colormap_names: Vec<&str> = ColorMap::all().iter().map(|c| c.name()).collect();
+    let size_policy_names: Vec<&str> = SizePolicy::all().iter().map(|p| p.name()).collect();
+    let disparity_type_names: Vec<&str> = DisparityType::all().iter().map(|t| t.name()).collect();
+    let subpixel_names: Vec<&str> = SubpixelFit::all().iter().map(|f| f.name()).collect();
+    let confidence_names: Vec<&str> = ConfidenceMeasure::all().iter().map(|m| m.name()).collect();
+
+    let matches = App::new("stereo_match")
+        .about("Computes a disparity map of a rectified stereo pair with OpenCL block matching")
+        .arg(Arg::with_name("left")
+             .short("l")
+             .long("left")
+             .value_name("FILE")
+             .default_value("../data/left.png"))
+        .arg(Arg::with_name("right")
+             .short("r")
+             .long("right")
+             .value_name("FILE")
+             .default_value("../data/right.png"))
+        .arg(Arg::with_name("size-policy")
+             .long("size-policy")
+             .value_name("NAME")
+             .help("How a right image of another size than the left one is handled")
+             .possible_values(&size_policy_names)
+             .default_value(SizePolicy::default().name()))
+        .arg(Arg::with_name("output")
+             .short("o")
+             .long("output")
+             .value_name("FILE")
+             .default_value("result.png"))
+        .arg(Arg::with_name("block-width")
+             .long("block-width")
+             .value_name("PIXELS")
+             .default_value("11")
+             .validator(is_positive))
+        .arg(Arg::with_name("block-height")
+             .long("block-height")
+             .value_name("PIXELS")
+             .default_value("11")
+             .validator(is_positive))
+        .arg(Arg::with_name("min-disparity")
+             .long("min-disparity")
+             .value_name("PIXELS")
+             .default_value("0")
+             .validator(is_non_negative))
+        .arg(Arg::with_name("max-disparity")
+             .long("max-disparity")
+             .value_name("PIXELS")
+             .help("Exclusive upper bound of the searched disparities [default: width / 4]")
+             .validator(is_positive))
+        .arg(Arg::with_name("density")
+             .long("density")
+             .value_name("NAME")
+             .help("One disparity per pixel, or per block for a quick preview")
+             .possible_values(&density_names)
+             .default_value(Density::default().name()))
+        .arg(Arg::with_name("cost")
+             .long("cost")
+             .value_name("FUNCTION")
+             .help("Per-pixel matching cost summed over each block")
+             .possible_values(&cost_function_names)
+             .default_value(CostFunction::default().name()))
+        .arg(Arg::with_name("transform-width")
+             .long("transform-width")
+             .value_name("PIXELS")
+             .help("Window width for --cost census and --cost rank")
+             .default_value("5")
+             .validator(is_positive))
+        .arg(Arg::with_name("transform-height")
+             .long("transform-height")
+             .value_name("PIXELS")
+             .help("Window height for --cost census and --cost rank")
+             .default_value("5")
+             .validator(is_positive))
+        .arg(Arg::with_name("aggregation")
+             .long("aggregation")
+             .value_name("NAME")
+             .help("Sum costs over blocks, directly or from integral images, or over color-shaped regions, smooth or filter them, or weight them by color")
+             .possible_values(&aggregation_names)
+             .default_value(Aggregation::default().name()))
+        .arg(Arg::with_name("sgm-paths")
+             .long("sgm-paths")
+             .value_name("COUNT")
+             .help("Path directions for --aggregation sgm")
+             .possible_values(&sgm_paths_names)
+             .default_value(SgmPaths::default().name()))
+        .arg(Arg::with_name("p1")
+             .long("p1")
+             .value_name("COST")
+             .help("Penalty of a one-pixel disparity change for --aggregation sgm")
+             .default_value("8"))
+        .arg(Arg::with_name("p2")
+             .long("p2")
+             .value_name("COST")
+             .help("Penalty of a larger disparity jump for --aggregation sgm")
+             .default_value("32"))
+        .arg(Arg::with_name("gamma-color")
+             .long("gamma-color")
+             .value_name("DISTANCE")
+             .help("RGB distance over which weights fall by e for --aggregation adaptive")
+             .default_value("30"))
+        .arg(Arg::with_name("gamma-proximity")
+             .long("gamma-proximity")
+             .value_name("PIXELS")
+             .help("Distance from the block center over which weights fall by e for --aggregation adaptive")
+             .default_value("15"))
+        .arg(Arg::with_name("cross-threshold")
+             .long("cross-threshold")
+             .value_name("VALUE")
+             .help("Channel difference that stops the arms of --aggregation cross")
+             .default_value("20"))
+        .arg(Arg::with_name("cross-arm")
+             .long("cross-arm")
+             .value_name("PIXELS")
+             .help("Longest arm for --aggregation cross")
+             .default_value("17")
+             .validator(is_non_negative))
+        .arg(Arg::with_name("guided-epsilon")
+             .long("guided-epsilon")
+             .value_name("VALUE")
+             .help("Regularization of --aggregation guided; larger smooths across more edges")
+             .default_value("10"))
+        .arg(Arg::with_name("backend")
+             .long("backend")
+             .value_name("NAME")
+             .help("Where matching runs; auto uses the CPU when no OpenCL platform is found")
+             .possible_values(&backend_names)
+             .default_value(Backend::default().name()))
+        .arg(Arg::with_name("cost-volume")
+             .long("cost-volume")
+             .value_name("NAME")
+             .help("Whether per-pixel costs are stored; auto streams them when the device cannot hold them")
+             .possible_values(&cost_volume_names)
+             .default_value(CostVolume::default().name()))
+        .arg(Arg::with_name("dispatch")
+             .long("dispatch")
+             .value_name("NAME")
+             .possible_values(&dispatch_names)
+             .default_value(DispatchStrategy::default().name()))
+        .arg(Arg::with_name("border")
+             .long("border")
+             .value_name("POLICY")
+             .help("What the left image reads as past its right edge")
+             .possible_values(&border_names)
+             .default_value(BorderPolicy::default().name()))
+        .arg(Arg::with_name("border-value")
+             .long("border-value")
+             .value_name("VALUE")
+             .help("Pixel value past the right edge for --border constant")
+             .default_value("0"))
+        .arg(Arg::with_name("disparity-type")
+             .long("disparity-type")
+             .value_name("TYPE")
+             .help("Element type of the disparities [default: u8, or u16 past 255, or f32 with --subpixel]")
+             .possible_values(&disparity_type_names))
+        .arg(Arg::with_name("subpixel")
+             .long("subpixel")
+             .value_name("FIT")
+             .help("Refine disparities to fractions of a pixel by fitting the costs around the best one")
+             .possible_values(&subpixel_names)
+             .default_value(SubpixelFit::default().name()))
+        .arg(Arg::with_name("uniqueness-ratio")
+             .long("uniqueness-ratio")
+             .value_name("PERCENT")
+             .help("Mark pixels invalid where a disparity not next to the best ties or costs less than this much more")
+             .default_value("0")
+             .validator(is_non_negative))
+        .arg(Arg::with_name("texture-threshold")
+             .long("texture-threshold")
+             .value_name("VARIANCE")
+             .help("Mark pixels invalid whose block varies less than this in squared gray levels")
+             .default_value("0")
+             .validator(is_non_negative))
+        .arg(Arg::with_name("left-right-check")
+             .long("left-right-check")
+             .value_name("DIFFERENCE")
+             .help("Also match left against right and mark pixels invalid where the disparities differ by more"))
+        .arg(Arg::with_name("median")
+             .long("median")
+             .value_name("SIZE")
+             .help("Replace each disparity by the median of this odd-sized square around it")
+             .default_value("0")
+             .validator(is_non_negative))
+        .arg(Arg::with_name("speckle-size")
+             .long("speckle-size")
+             .value_name("PIXELS")
+             .help("Mark regions of at most this many similar disparities invalid")
+             .validator(is_non_negative))
+        .arg(Arg::with_name("speckle-difference")
+             .long("speckle-difference")
+             .value_name("DIFFERENCE")
+             .help("Largest disparity step within a speckle region")
+             .default_value("1"))
+        .arg(Arg::with_name("confidence")
+             .long("confidence")
+             .value_name("MEASURE")
+             .help("Also save a grayscale map of how sure each disparity is, black for invalid ones")
+             .possible_values(&confidence_names))
+        .arg(Arg::with_name("confidence-output")
+             .long("confidence-output")
+             .value_name("FILE")
+             .default_value("confidence.png"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .value_name("FORMAT")
+             .possible_values(&format_names)
+             .default_value(OutputFormat::default().name()))
+        .arg(Arg::with_name("colormap")
+             .long("colormap")
+             .value_name("NAME")
+             .possible_values(&colormap_names)
+             .default_value(ColorMap::default().name()))
+        .get_matches();
+
+    let block_w = value_t_or_exit!(matches, "block-width", usize);
+    let block_h = value_t_or_exit!(matches, "block-height", usize);
+    let min_disparity = value_t_or_exit!(matches, "min-disparity", usize);
+    let density = value_t_or_exit!(matches, "density", Density);
+    let cost_function = value_t_or_exit!(matches, "cost", CostFunction);
+    let transform_w = value_t_or_exit!(matches, "transform-width", usize);
+    let transform_h = value_t_or_exit!(matches, "transform-height", usize);
+    let aggregation = value_t_or_exit!(matches, "aggregation", Aggregation);
+    let sgm_paths = value_t_or_exit!(matches, "sgm-paths", SgmPaths);
+    let p1 = value_t_or_exit!(matches, "p1", u32);
+    let p2 = value_t_or_exit!(matches, "p2", u32);
+    let gamma_color = value_t_or_exit!(matches, "gamma-color", f32);
+    let gamma_proximity = value_t_or_exit!(matches, "gamma-proximity", f32);
+    let cross_threshold = value_t_or_exit!(matches, "cross-threshold", u8);
+    let cross_arm = value_t_or_exit!(matches, "cross-arm", usize);
+    let guided_epsilon = value_t_or_exit!(matches, "guided-epsilon", f32);
+    let backend = value_t_or_exit!(matches, "backend", Backend);
+    let cost_volume = value_t_or_exit!(matches, "cost-volume", CostVolume);
+    let dispatch = value_t_or_exit!(matches, "dispatch", DispatchStrategy);
+    let border = value_t_or_exit!(matches, "border", BorderPolicy);
+    let border_value = value_t_or_exit!(matches, "border-value", u8);
+    let subpixel_fit = value_t_or_exit!(matches, "subpixel", SubpixelFit);
+    let uniqueness_ratio = value_t_or_exit!(matches, "uniqueness-ratio", u32);
+    let texture_threshold = value_t_or_exit!(matches, "texture-threshold", u32);
+    let median_size = value_t_or_exit!(matches, "median", usize);
+    let format = value_t_or_exit!(matches, "format", OutputFormat);
+    let colormap = value_t_or_exit!(matches, "colormap", ColorMap);
+    let size_policy = value_t_or_exit!(matches, "size-policy", SizePolicy);
+    let output_file_name = matches.value_of("output").unwrap();
+
+    let start_time = PreciseTime::now();
+    let pair = StereoPair::open_with(
+        matches.value_of("left").unwrap(),
+        matches.value_of("right").unwrap(),
+        size_policy,
+    ).unwrap_or_else(|error| exit_with(error));
+
+    let loaded_image_time = PreciseTime::now();
+
+    let max_disparity = if matches.is_present("max-disparity") {
+        value_t_or_exit!(matches, "max-disparity", usize)
+    } else {
+        pair.width / 4
+    };
+
+    let mut matcher = StereoMatcher::new()
+        .block_size(block_w, block_h)
+        .min_disparity(min_disparity)
+        .max_disparity(max_disparity)
+        .density(density)
+        .cost_function(cost_function)
+        .transform_window(transform_w, transform_h)
+        .aggregation(aggregation)
+        .sgm_paths(sgm_paths)
+        .sgm_penalties(p1, p2)
+        .adaptive_weights(gamma_color, gamma_proximity)
+        .cross_arms(cross_threshold, cross_arm)
+        .guided_filter_epsilon(guided_epsilon)
+        .backend(backend)
+        .cost_volume(cost_volume)
+        .dispatch(dispatch)
+        .border(border)
+        .border_value(border_value)
+        .subpixel_fit(subpixel_fit)
+        .uniqueness_ratio(uniqueness_ratio)
+        .texture_threshold(texture_threshold)
+        .median_filter(median_size);
+    if matches.is_present("disparity-type") {
+        matcher = matcher.disparity_type(value_t_or_exit!(matches, "disparity-type", DisparityType));
+    }
+    if matches.is_present("left-right-check") {
+        matcher = matcher.left_right_check(value_t_or_exit!(matches, "left-right-check", f32));
+    }
+    if matches.is_present("speckle-size") {
+        matcher = matcher.speckle_filter(
+            value_t_or_exit!(matches, "speckle-size", usize),
+            value_t_or_exit!(matches, "speckle-difference", f32),
+        );
+    }
+    if matches.is_present("confidence") {
+        matcher = matcher.confidence(value_t_or_exit!(matches, "confidence", ConfidenceMeasure));
+    }
+    let disparity = matcher.compute(&pair).unwrap_or_else(|error| exit_with(error));
+
+    let got_result_time = PreciseTime::now();
+
+    disparity.save(output_file_name, format, colormap).unwrap_or_else(|error| exit_with(error));
+    if let Some(ref confidence) = disparity.confidence {
+        let confidence_file_name = matches.value_of("confidence-output").unwrap();
+        confidence.save(confidence_file_name).unwrap_or_else(|error| exit_with(error));
+    }
+
+    let created_result_image_time = PreciseTime::now();
+
+    println!("Dispatch {}", dispatch);
+    println!("Load image {} sec", start_time.to(loaded_image_time));
+    println!("Get result {} sec", loaded_image_time.to(got_result_time));
+    println!("Create resutl image {} sec", got_result_time.to(created_result_image_time));
+    println!("Total {} sec", start_time.to(created_result_image_time));
+}